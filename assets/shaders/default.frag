@@ -1,8 +1,44 @@
 #version 450
 
 layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragUV;
 layout(location = 0) out vec4 outColor;
+layout(binding = 1) uniform sampler2D texSampler;
+
+// The color space the swapchain presents in: 0 sRGB, 1 HDR10, 2 scRGB.
+layout(constant_id = 100) const uint OUTPUT_COLOR_SPACE = 0;
+
+// Brightness of SDR white on HDR displays, in nits.
+const float PAPER_WHITE = 203.0;
+
+vec3 bt709_to_bt2020(vec3 color) {
+    return mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956) * color;
+}
+
+// ST 2084 inverse EOTF, from nits to the PQ signal.
+vec3 pq_encode(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
 
 void main() {
-    outColor = vec4(fragColor, 1.0);
-}
\ No newline at end of file
+    vec4 color = texture(texSampler, fragUV) * vec4(fragColor, 1.0);
+
+    if (OUTPUT_COLOR_SPACE == 1) {
+        color.rgb = pq_encode(bt709_to_bt2020(color.rgb) * PAPER_WHITE);
+    } else if (OUTPUT_COLOR_SPACE == 2) {
+        // scRGB shares the sRGB primaries and puts 1.0 at 80 nits.
+        color.rgb *= PAPER_WHITE / 80.0;
+    }
+
+    outColor = color;
+}
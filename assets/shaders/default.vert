@@ -1,16 +1,21 @@
 #version 450
 
-layout(location = 0) in vec2 position;
+layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 color;
+layout(location = 2) in vec2 uv;
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragUV;
 layout(binding = 0) uniform Camera {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } camera;
+layout(push_constant) uniform Object {
+    mat4 model;
+} object;
 
 void main() {
-    gl_Position = camera.proj * camera.view * camera.model * vec4(position, 0.0, 1.0);
+    gl_Position = camera.proj * camera.view * object.model * vec4(position, 1.0);
     fragColor = color;
+    fragUV = uv;
 }
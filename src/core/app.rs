@@ -1,174 +1,201 @@
-use std::{ffi::CStr, i8};
-
 use ash::vk;
 
 use gpu_allocator::vulkan;
 use raw_window_handle::HasRawDisplayHandle;
 
 use crate::core::buffers::{
-    create_descriptor_sets, create_index_buffer, create_uniform_buffers, create_vertex_buffer,
+    create_allocator, create_buffer_staging_from_slice, create_descriptor_sets,
+    create_index_buffer, create_storage_buffer, create_uniform_buffers, create_vertex_buffer,
+    destroy_buffer, upload_buffer,
 };
+use crate::core::builder::{AppBuilder, Callbacks, EngineConfig};
 use crate::core::camera::Camera;
+use crate::core::controller::CameraController;
 use crate::core::debug::create_debug;
 
-use crate::core::geometry::{QUAD_INDICES, QUAD_VERTICES};
-
 use crate::core::{
-    commands::create_command_pool, device::create_device, framebuffer::create_framebuffers,
-    pipeline::create_pipeline, surface::create_surface, swapchain::create_swapchain,
-    sync::create_sync,
+    commands::{create_command_pool, create_transfer_queue},
+    compute::{
+        create_compute_pipeline, destroy_compute_pipeline, prepare_storage_image, record_dispatch,
+        submit_once,
+    },
+    device::create_device,
+    images::{create_storage_image, destroy_image},
+    instance::create_instance,
+    pipeline::destroy_pipeline,
+    pipeline_cache::{cache_path, create_pipeline_cache, save_pipeline_cache},
+    surface::create_surface,
+    sync::create_frames,
+    textures::{create_default_texture, create_texture_from_file, destroy_texture},
 };
 
 use super::buffers::Buffer;
-use super::commands::CommandInfo;
+use super::compute::{ComputePipeline, ComputeResource, DispatchCommand};
 
 use super::commands::record_buffer;
 use super::debug::DebugInfo;
 use super::device::DeviceInfo;
-use super::pipeline::PipelineInfo;
+use super::draw::{DrawCommand, DrawList};
+use super::dynamic::{DeletionQueue, DynamicBuffer, DynamicBufferId};
+use super::error::{Result, VkcrError};
+use super::images::Image;
+use super::instance::InstanceInfo;
+use super::mesh::Mesh;
+use super::pipeline::{PipelineBuilder, PipelineInfo};
+use super::reflect::ShaderLayout;
+use super::reload::ShaderWatcher;
 use super::surface::SurfaceInfo;
-use super::swapchain::SwapchainInfo;
-use super::sync::SyncInfo;
+use super::swapchain::{set_hdr_metadata, ColorOutput, HdrMetadata, PresentMode, Swapchain};
+use super::sync::FrameContext;
+use super::textures::Texture;
+use super::timeline::Timeline;
+use super::upload::{UploadHandle, UploadManager, STAGING_SIZE};
 
 extern crate env_logger;
 
-const APP_NAME: &str = "VKCR\0";
-const ENGINE_NAME: &str = "VKCR Renderer\0";
-
-const API_DUMP: &str = "VK_LAYER_LUNARG_api_dump\0";
-const RENDERDOC_CAPTURE: &str = "VK_LAYER_RENDERDOC_Capture\0";
-
-const VALIDATION: &str = "VK_LAYER_KHRONOS_validation\0";
-
-pub const MAX_CONCURRENT_FRAMES: u8 = 9;
+/// Frames recorded ahead of the GPU unless [`AppBuilder::frames_in_flight`] asks for another
+/// count.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
 
 pub struct App {
     window: winit::window::Window,
     instance: ash::Instance,
     device_info: DeviceInfo,
     surface_info: SurfaceInfo,
-    swapchain_info: SwapchainInfo,
+    swapchain: Swapchain,
+    /// Set when the surface changed and the swapchain has to be recreated before the next
+    /// frame. Stays set while the window is minimized.
+    swapchain_dirty: bool,
+    /// Swapchains that were replaced while frames in flight may still use them, keyed by
+    /// graphics timeline value.
+    retired_swapchains: DeletionQueue<Swapchain>,
     pipeline_info: PipelineInfo,
-    framebuffers: Vec<vk::Framebuffer>,
-    command_info: CommandInfo,
-    sync_info: SyncInfo,
+    pipeline: PipelineBuilder,
+    /// The state of every material pipeline, in the order they follow the main pipeline in
+    /// `pipeline_info.pipeline`.
+    materials: Vec<PipelineBuilder>,
+    pipeline_cache: vk::PipelineCache,
+    /// Where `pipeline_cache` is written back on shutdown, if anywhere.
+    pipeline_cache_path: Option<std::path::PathBuf>,
+    shader_watcher: Option<ShaderWatcher>,
+    compute_pipelines: Vec<ComputePipeline>,
+    /// Recorded before the render pass of the next frame, then cleared.
+    dispatches: Vec<DispatchCommand>,
+    storage_images: Vec<Image>,
+    /// Command pool of the dedicated compute queue, if the device has one.
+    compute_command_pool: Option<vk::CommandPool>,
+    /// Progress of the dedicated compute queue, if the device has one.
+    compute_timeline: Option<Timeline>,
+    texture: Option<Texture>,
+    /// Pool of the graphics queue, which the frames' command buffers come from.
+    command_pool: vk::CommandPool,
+    /// Progress of the graphics queue, which every frame advances.
+    graphics_timeline: Timeline,
+    /// Streams buffer data to the GPU on the transfer queue.
+    uploads: UploadManager,
+    /// What each frame in flight records into and waits on, indexed by `current_frame`.
+    frames: Vec<FrameContext>,
+    present_mode: PresentMode,
+    image_count: Option<u32>,
+    color_outputs: Vec<ColorOutput>,
+    hdr_metadata: HdrMetadata,
+    /// Loaded when the device supports `VK_EXT_hdr_metadata`.
+    hdr_metadata_fn: Option<vk::ExtHdrMetadataFn>,
     is_exiting: bool,
     current_frame: usize,
+    dynamic_buffers: Vec<DynamicBuffer>,
+    /// Buffers replaced while frames in flight may still read them, keyed by graphics
+    /// timeline value.
+    retired_buffers: DeletionQueue<Buffer>,
     debug_info: DebugInfo,
     allocator: Option<vulkan::Allocator>,
     buffers: Option<Vec<Buffer>>,
-    descriptor_sets: Vec<vk::DescriptorSet>,
     descriptor_pool: vk::DescriptorPool,
     descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
     total_delta: f32,
+    camera: Camera,
+    controller: Option<CameraController>,
+    draw_list: DrawList,
+    /// Vertex buffer of the configured mesh, whose draw is the only one spun without an update
+    /// callback.
+    default_mesh: vk::Buffer,
+    callbacks: Callbacks,
+    exit_requested: bool,
+    last_frame: std::time::Instant,
 }
 
 impl App {
-    pub fn init() {
-        let mut instance_extensions: Vec<*const i8> =
-            vec![ash::extensions::ext::DebugUtils::name().as_ptr()];
-
-        let enable_api_dump = std::env::var("ENABLE_API_DUMP").unwrap_or_else(|_| "0".to_string());
-        let enable_renderdoc_capture =
-            std::env::var("ENABLE_RENDERDOC_CAPTURE").unwrap_or_else(|_| "0".to_string());
-        let enable_validation =
-            std::env::var("ENABLE_VALIDATION").unwrap_or_else(|_| "0".to_string());
+    /// Runs the engine with the default configuration.
+    pub fn init() -> Result<()> {
+        AppBuilder::new().run()
+    }
 
+    /// Only returns if initialization fails; afterwards the event loop owns the process.
+    pub(crate) fn run_with(config: EngineConfig, callbacks: Callbacks) -> Result<()> {
         env_logger::init();
 
         let event_loop = winit::event_loop::EventLoop::new();
 
         let window = winit::window::WindowBuilder::new()
-            .with_title("VKCR")
+            .with_title(&config.title)
             .with_min_inner_size(winit::dpi::LogicalSize {
-                height: 300,
-                width: 300,
+                height: config.min_height,
+                width: config.min_width,
             })
-            .build(&event_loop)
-            .expect("Failed to create window");
-
-        let application_info = vk::ApplicationInfo::builder()
-            .application_name(unsafe { CStr::from_ptr(APP_NAME.as_ptr() as *const i8) })
-            .application_version(vk::make_api_version(0, 0, 1, 0))
-            .engine_name(unsafe { CStr::from_ptr(ENGINE_NAME.as_ptr() as *const i8) })
-            .engine_version(vk::make_api_version(0, 0, 1, 0))
-            .api_version(vk::make_api_version(0, 1, 3, 239));
+            .build(&event_loop)?;
 
-        let entry = ash::Entry::linked();
+        let InstanceInfo { entry, instance } = create_instance(
+            ash_window::enumerate_required_extensions(window.raw_display_handle())?,
+        )?;
 
-        let layers = entry
-            .enumerate_instance_layer_properties()
-            .expect("Failed to enumerate instance layer properties");
+        let debug_info = create_debug(&entry, &instance)?;
 
-        debug!("Available layers: ");
+        let surface_info = create_surface(&window, &entry, &instance)?;
 
-        for layer in layers.iter() {
-            debug!(
-                "   {}",
-                std::str::from_utf8(unsafe {
-                    &*(layer.layer_name.as_slice() as *const [i8] as *const [u8])
-                })
-                .expect("Failed to create string from layer name")
+        let requirements = config
+            .device
+            .clone()
+            .extension(
+                ash::extensions::khr::Swapchain::name()
+                    .to_str()
+                    .expect("Failed to convert extension name"),
+            )
+            .optional_extension(
+                vk::ExtHdrMetadataFn::name()
+                    .to_str()
+                    .expect("Failed to convert extension name"),
             );
-        }
-
-        let mut instance_layers: Vec<*const i8> = Vec::new();
-
-        if enable_api_dump == "1" {
-            instance_layers.push(API_DUMP.as_ptr() as *const i8);
-        }
-
-        if enable_renderdoc_capture == "1" {
-            instance_layers.push(RENDERDOC_CAPTURE.as_ptr() as *const i8);
-        }
-
-        if enable_validation == "1" {
-            instance_layers.push(VALIDATION.as_ptr() as *const i8);
-        }
-
-        for extension in ash_window::enumerate_required_extensions(window.raw_display_handle())
-            .expect("Failed to enumerate required extensions")
-        {
-            instance_extensions.push(*extension);
-        }
-
-        let instance_create_info = vk::InstanceCreateInfo::builder()
-            .application_info(&application_info)
-            .enabled_extension_names(instance_extensions.as_slice())
-            .enabled_layer_names(instance_layers.as_slice());
 
-        let instance: ash::Instance = unsafe {
-            entry
-                .create_instance(&instance_create_info, None)
-                .expect("Failed to create instance")
-        };
-
-        let debug_info = create_debug(&entry, &instance);
-
-        let device_info = create_device(&instance);
+        let device_info = create_device(&instance, Some(&surface_info), &requirements)?;
 
-        let mut allocator = vulkan::Allocator::new(&vulkan::AllocatorCreateDesc {
-            instance: instance.clone(),
-            device: device_info.clone().device,
-            physical_device: device_info
-                .logical_devices
-                .first()
-                .expect("Failed to get first logical device")
-                .physical_device,
-            debug_settings: Default::default(),
-            buffer_device_address: false,
-        })
-        .expect("Failed to create allocator");
+        let mut allocator = create_allocator(&instance, &device_info)?;
 
-        let surface_info = create_surface(&window, &entry, &instance);
+        let hdr_metadata_fn = device_info
+            .extensions
+            .iter()
+            .any(|extension| extension.as_bytes() == vk::ExtHdrMetadataFn::name().to_bytes())
+            .then(|| {
+                vk::ExtHdrMetadataFn::load(|name| unsafe {
+                    std::mem::transmute(
+                        instance.get_device_proc_addr(device_info.device.handle(), name.as_ptr()),
+                    )
+                })
+            });
 
-        let swapchain_info = create_swapchain(
-            device_info.clone(),
-            surface_info.clone(),
+        // Windows are never created minimized, so the surface has an area.
+        let mut swapchain = Swapchain::create(
+            &device_info,
+            &surface_info,
             &instance,
-            &window,
-            None,
-        );
+            window_extent(&window),
+            &config.color_outputs,
+            config.present_mode,
+            config.image_count,
+            vk::SwapchainKHR::null(),
+        )?
+        .ok_or(VkcrError::Vulkan(vk::Result::ERROR_OUT_OF_DATE_KHR))?;
+
+        let frames_in_flight = config.frames_in_flight.max(1);
 
         let command_info = create_command_pool(
             device_info
@@ -176,122 +203,203 @@ impl App {
                 .first()
                 .expect("Failed to get queue family"),
             &device_info.device,
-        );
+            frames_in_flight as u32,
+        )?;
+
+        let mut uploads = UploadManager::new(
+            &device_info.device,
+            &mut allocator,
+            create_transfer_queue(&device_info)?,
+            Timeline::new(&device_info.device, device_info.timeline_semaphores)?,
+            STAGING_SIZE,
+        )?;
 
         let uniform_buffers = create_uniform_buffers(
-            Camera {
-                model: cgmath::Matrix4::from_scale(1.0),
-                view: cgmath::Matrix4::from_scale(1.0),
-                proj: cgmath::Matrix4::from_scale(1.0),
-            },
+            config.camera,
             &mut allocator,
             &device_info.device,
-            command_info.command_pool,
-            device_info.queue,
-        );
+            frames_in_flight,
+        )?;
 
-        let (descriptor_sets, descriptor_pool, descriptor_set_layouts) =
-            create_descriptor_sets(&device_info.device, &uniform_buffers, Camera::default());
+        let texture = match &config.texture {
+            Some(path) => {
+                create_texture_from_file(path, &mut allocator, &device_info.device, &mut uploads)?
+            }
+            None => create_default_texture(&mut allocator, &device_info.device, &mut uploads)?,
+        };
 
-        let pipeline_info = create_pipeline(
+        let shader_layout = ShaderLayout::load(&config.pipeline.shader)?;
+
+        let (descriptor_sets, descriptor_pool, descriptor_set_layouts) = create_descriptor_sets(
             &device_info.device,
-            "assets/shaders/default",
-            &swapchain_info.extent,
-            swapchain_info.current_format,
+            &shader_layout,
+            &uniform_buffers,
+            Camera::default(),
+            &texture,
+        )?;
+
+        let pipeline_cache_path = config.cache_dir.as_deref().map(cache_path);
+        let pipeline_cache = create_pipeline_cache(&device_info, pipeline_cache_path.as_deref())?;
+
+        let pipeline_info = config.pipeline.build(
+            &device_info,
+            pipeline_cache,
+            &swapchain.extent,
+            swapchain.format,
+            swapchain.output,
+            vk::ImageLayout::PRESENT_SRC_KHR,
             &descriptor_set_layouts,
-        );
+        )?;
 
-        let framebuffers = create_framebuffers(
-            swapchain_info.clone(),
-            pipeline_info.clone(),
+        swapchain.attach(
             &device_info.device,
-        );
+            &mut allocator,
+            pipeline_info.samples,
+            pipeline_info.render_pass,
+        )?;
 
         let mut buffers = Vec::new();
+        let mut draw_list = DrawList::new();
 
         let vertex_buffer = create_vertex_buffer(
-            QUAD_VERTICES,
+            &config.mesh.vertices,
             &mut allocator,
             &device_info.device,
-            command_info.command_pool,
-            device_info.queue,
-        );
-
-        buffers.push(vertex_buffer);
+            &mut uploads,
+        )?;
 
         let index_buffer = create_index_buffer(
-            QUAD_INDICES,
+            &config.mesh.indices,
             &mut allocator,
             &device_info.device,
-            command_info.command_pool,
-            device_info.queue,
-        );
+            &mut uploads,
+        )?;
+
+        draw_list.push(DrawCommand::indexed(
+            vertex_buffer.buffer,
+            index_buffer.buffer,
+            config
+                .mesh
+                .indices
+                .len()
+                .try_into()
+                .expect("Failed to convert to u32"),
+            *pipeline_info
+                .pipeline
+                .first()
+                .expect("Failed to get pipeline"),
+        ));
 
+        let default_mesh = vertex_buffer.buffer;
+        buffers.push(vertex_buffer);
         buffers.push(index_buffer);
 
-        let sync_info = create_sync(&device_info.device);
-
-        let mut last_modification_time = std::time::Duration::from_millis(0);
-        for entry in glob::glob("assets/shaders/*.spv").expect("Failed to get assets/shaders/*.spv")
-        {
-            match entry {
-                Ok(path) => {
-                    let metadata = std::fs::metadata(path).expect("Failed to get file metadata");
-                    let modification_time = metadata
-                        .modified()
-                        .expect("Failed to get modification time")
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .expect("Failed to get time since unix epoch")
-                        .as_millis();
-                    if modification_time > last_modification_time.as_millis() {
-                        last_modification_time =
-                            std::time::Duration::from_millis(modification_time.try_into().unwrap());
-                    }
-                }
-                Err(e) => panic!("{}", e),
+        let frames = create_frames(
+            &device_info.device,
+            command_info.command_buffers,
+            uniform_buffers,
+            descriptor_sets,
+        )?;
+
+        let compute_command_pool = match device_info.compute_family {
+            Some(family) => {
+                let command_pool_info = vk::CommandPoolCreateInfo::builder()
+                    .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                    .queue_family_index(family);
+                Some(unsafe {
+                    device_info
+                        .device
+                        .create_command_pool(&command_pool_info, None)
+                }?)
             }
-        }
+            None => None,
+        };
 
-        for buffer in uniform_buffers {
-            buffers.push(buffer);
-        }
+        let graphics_timeline =
+            Timeline::new(&device_info.device, device_info.timeline_semaphores)?;
+        let compute_timeline = compute_command_pool
+            .map(|_| Timeline::new(&device_info.device, device_info.timeline_semaphores))
+            .transpose()?;
+
+        let shader_watcher = config
+            .hot_reload
+            .then(|| ShaderWatcher::new(&config.pipeline.shader));
 
         let game = App {
             window,
             instance,
             device_info,
             surface_info,
-            swapchain_info,
+            swapchain,
+            swapchain_dirty: false,
+            retired_swapchains: DeletionQueue::new(),
             pipeline_info,
-            framebuffers,
-            command_info,
-            sync_info,
+            pipeline: config.pipeline,
+            materials: Vec::new(),
+            pipeline_cache,
+            pipeline_cache_path,
+            shader_watcher,
+            compute_pipelines: Vec::new(),
+            dispatches: Vec::new(),
+            storage_images: Vec::new(),
+            compute_command_pool,
+            compute_timeline,
+            texture: Some(texture),
+            command_pool: command_info.command_pool,
+            graphics_timeline,
+            uploads,
+            frames,
+            present_mode: config.present_mode,
+            image_count: config.image_count,
+            color_outputs: config.color_outputs,
+            hdr_metadata: config.hdr_metadata,
+            hdr_metadata_fn,
             is_exiting: false,
             current_frame: 0,
+            dynamic_buffers: Vec::new(),
+            retired_buffers: DeletionQueue::new(),
             allocator: Some(allocator),
             debug_info,
-            descriptor_sets,
             descriptor_pool,
             descriptor_set_layouts,
             buffers: Some(buffers),
             total_delta: 0.1,
+            camera: config.camera,
+            controller: config.camera_mode.map(CameraController::new),
+            draw_list,
+            default_mesh,
+            callbacks,
+            exit_requested: false,
+            last_frame: std::time::Instant::now(),
         };
 
-        game.run(event_loop);
+        game.send_hdr_metadata();
+
+        game.run(event_loop)
     }
 
-    fn run(mut self, event_loop: winit::event_loop::EventLoop<()>) {
+    fn run(mut self, event_loop: winit::event_loop::EventLoop<()>) -> ! {
         event_loop.run(move |event, _, control_flow| {
             *control_flow = winit::event_loop::ControlFlow::Poll;
 
-            match event {
-                winit::event::Event::WindowEvent {
-                    window_id,
-                    event: winit::event::WindowEvent::KeyboardInput { input, .. },
-                } if window_id == self.window.id() && input.virtual_keycode.is_some() => {
-                    self.handle_input(input.virtual_keycode)
+            if let winit::event::Event::WindowEvent {
+                window_id,
+                event: ref window_event,
+            } = event
+            {
+                if window_id == self.window.id() {
+                    if let Some(controller) = &mut self.controller {
+                        controller.handle_event(window_event);
+                    }
+
+                    if let Some(mut input) = self.callbacks.input.take() {
+                        input(&mut self, window_event);
+                        self.callbacks.input = Some(input);
+                    }
                 }
+            }
 
+            match event {
                 winit::event::Event::WindowEvent {
                     window_id,
                     event: winit::event::WindowEvent::CloseRequested,
@@ -300,7 +408,12 @@ impl App {
                     *control_flow = winit::event_loop::ControlFlow::Exit;
                 }
 
-                winit::event::Event::RedrawRequested(_) => self.render(),
+                winit::event::Event::RedrawRequested(_) => {
+                    if let Err(e) = self.render() {
+                        error!("Failed to render frame: {e}");
+                        self.exit();
+                    }
+                }
 
                 winit::event::Event::MainEventsCleared => {
                     self.window.request_redraw();
@@ -313,63 +426,445 @@ impl App {
                 winit::event::Event::WindowEvent {
                     window_id,
                     event: winit::event::WindowEvent::Resized(_),
-                } if window_id == self.window.id() => self.resize(),
+                } if window_id == self.window.id() => {
+                    self.swapchain_dirty = true;
+                }
 
                 _ => (),
             }
+
+            if self.exit_requested && !self.is_exiting {
+                self.cleanup();
+                *control_flow = winit::event_loop::ControlFlow::Exit;
+            }
         });
     }
 
-    fn update(&self, current_image: usize) {
-        let camera = Camera {
-            model: cgmath::Matrix4::from_axis_angle(
-                cgmath::Vector3 {
-                    x: 0.0,
-                    y: 1.0,
-                    z: 0.0,
-                },
-                cgmath::Deg(self.total_delta / 10000.0),
-            ),
-            view: cgmath::Matrix4::look_at_rh(
-                cgmath::Point3 {
-                    x: 0.0,
-                    y: 1.0,
-                    z: 2.0,
-                },
-                cgmath::Point3 {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-                cgmath::Vector3 {
-                    x: 0.0,
-                    y: 1.0,
-                    z: 0.0,
-                },
+    pub fn window(&self) -> &winit::window::Window {
+        &self.window
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.swapchain.extent
+    }
+
+    /// The present mode the swapchain uses, after falling back from the requested one.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.swapchain.present_mode
+    }
+
+    /// How many frames are recorded ahead of the GPU, which is also how many copies of
+    /// per-frame resources such as dynamic buffers exist.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The graphics timeline value the latest frame signals once the GPU is done with it and
+    /// with everything submitted to the graphics queue before it.
+    pub fn submitted_value(&self) -> u64 {
+        self.graphics_timeline.submitted()
+    }
+
+    /// Whether the graphics queue has reached `value`, such as one returned by
+    /// [`App::submitted_value`], without blocking.
+    pub fn is_complete(&mut self, value: u64) -> Result<bool> {
+        self.graphics_timeline
+            .is_complete(&self.device_info.device, value)
+    }
+
+    /// Blocks until the graphics queue has reached `value`.
+    pub fn wait_for_value(&mut self, value: u64) -> Result<()> {
+        self.graphics_timeline.wait(&self.device_info.device, value)
+    }
+
+    /// The color space frames are presented in, which fragment shaders also get as
+    /// [`COLOR_OUTPUT_CONSTANT_ID`](super::swapchain::COLOR_OUTPUT_CONSTANT_ID).
+    pub fn color_output(&self) -> ColorOutput {
+        self.swapchain.output
+    }
+
+    /// Switches between vsync, low latency and uncapped presentation by recreating the
+    /// swapchain.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> Result<()> {
+        self.present_mode = mode;
+        self.recreate_swapchain()
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// Present when a camera mode was configured. It overwrites the camera before the update
+    /// callback runs, so the callback can still adjust the result.
+    pub fn controller(&self) -> Option<&CameraController> {
+        self.controller.as_ref()
+    }
+
+    pub fn controller_mut(&mut self) -> Option<&mut CameraController> {
+        self.controller.as_mut()
+    }
+
+    /// Starts out with the configured mesh; games add, remove or replace entries freely.
+    pub fn draw_list(&self) -> &DrawList {
+        &self.draw_list
+    }
+
+    pub fn draw_list_mut(&mut self) -> &mut DrawList {
+        &mut self.draw_list
+    }
+
+    /// Uploads a mesh to the GPU and returns a draw command for it with the default pipeline.
+    /// The copies are submitted with the next frame, which waits for them before drawing. The
+    /// buffers are freed when the engine shuts down.
+    pub fn upload_mesh(&mut self, mesh: &Mesh) -> Result<DrawCommand> {
+        let (command, _) = self.upload_mesh_async(mesh)?;
+        Ok(command)
+    }
+
+    /// Like [`App::upload_mesh`], but also returns a handle that [`App::is_resident`] reports
+    /// done once the copies have completed.
+    pub fn upload_mesh_async(&mut self, mesh: &Mesh) -> Result<(DrawCommand, UploadHandle)> {
+        let allocator = self.allocator.as_mut().expect("Failed to get allocator");
+
+        let (vertex_buffer, _) = upload_buffer(
+            bytemuck::cast_slice(&mesh.vertices),
+            allocator,
+            &self.device_info.device,
+            &mut self.uploads,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            "Vertex",
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+
+        // Uploads complete in order, so the later handle covers the vertices too.
+        let (index_buffer, upload) = upload_buffer(
+            bytemuck::cast_slice(&mesh.indices),
+            allocator,
+            &self.device_info.device,
+            &mut self.uploads,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            "Index",
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+
+        let command = DrawCommand::indexed(
+            vertex_buffer.buffer,
+            index_buffer.buffer,
+            mesh.indices
+                .len()
+                .try_into()
+                .expect("Failed to convert to u32"),
+            *self
+                .pipeline_info
+                .pipeline
+                .first()
+                .expect("Failed to get pipeline"),
+        );
+
+        let buffers = self.buffers.as_mut().expect("Failed to get buffers");
+        buffers.push(vertex_buffer);
+        buffers.push(index_buffer);
+
+        Ok((command, upload))
+    }
+
+    /// Whether the upload behind `upload` has completed. Finished uploads are picked up once
+    /// per frame.
+    pub fn is_resident(&self, upload: UploadHandle) -> bool {
+        self.uploads.is_resident(upload)
+    }
+
+    /// Blocks until the upload behind `upload` has completed.
+    pub fn wait_upload(&mut self, upload: UploadHandle) -> Result<()> {
+        self.uploads.wait(
+            &self.device_info.device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            upload,
+        )
+    }
+
+    /// Creates a pipeline for a material and returns it for use in draw commands. It shares the
+    /// render pass, descriptor sets and push constants of the main pipeline, is rebuilt when
+    /// shaders are hot-reloaded and destroyed when the engine shuts down.
+    pub fn create_material(&mut self, material: PipelineBuilder) -> Result<vk::Pipeline> {
+        let pipeline = material.build_variant(
+            &self.device_info,
+            self.pipeline_cache,
+            &self.swapchain.extent,
+            &self.pipeline_info,
+        )?;
+
+        self.pipeline_info.pipeline.push(pipeline);
+        self.materials.push(material);
+
+        Ok(pipeline)
+    }
+
+    /// Creates a compute pipeline from `<shader>_c.spv` and returns it for use in
+    /// [`DispatchCommand`]s. It is destroyed when the engine shuts down.
+    pub fn create_compute_pipeline(&mut self, shader: &str) -> Result<vk::Pipeline> {
+        let compute =
+            create_compute_pipeline(&self.device_info.device, self.pipeline_cache, shader)?;
+        let pipeline = compute.pipeline;
+        self.compute_pipelines.push(compute);
+        Ok(pipeline)
+    }
+
+    /// Binds `resource` to `binding` of `set` in a compute pipeline. Waits for the frames in
+    /// flight to finish first, so bind once up front rather than every frame.
+    pub fn bind_compute(
+        &mut self,
+        pipeline: vk::Pipeline,
+        set: u32,
+        binding: u32,
+        resource: ComputeResource,
+    ) -> Result<()> {
+        let compute = self
+            .compute_pipelines
+            .iter()
+            .find(|compute| compute.pipeline == pipeline)
+            .expect("Failed to find compute pipeline");
+
+        unsafe { self.device_info.device.device_wait_idle() }?;
+
+        compute.bind(&self.device_info.device, set, binding, resource)
+    }
+
+    /// Uploads `data` to a GPU-only buffer with `usage` and returns it. It is freed when the
+    /// engine shuts down.
+    pub fn create_buffer<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<vk::Buffer> {
+        let buffer = create_buffer_staging_from_slice(
+            data,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            &self.device_info.device,
+            &mut self.uploads,
+            usage,
+            "Static",
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+
+        let handle = buffer.buffer;
+        self.buffers
+            .as_mut()
+            .expect("Failed to get buffers")
+            .push(buffer);
+        Ok(handle)
+    }
+
+    /// Creates a host-visible buffer with `usage` that is rewritten with
+    /// [`App::write_dynamic_buffer`], for data that changes from frame to frame. It is freed
+    /// when the engine shuts down.
+    pub fn create_dynamic_buffer(
+        &mut self,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+    ) -> DynamicBufferId {
+        self.dynamic_buffers
+            .push(DynamicBuffer::new(name, usage, self.frames.len()));
+        DynamicBufferId(self.dynamic_buffers.len() - 1)
+    }
+
+    /// Replaces the contents of a dynamic buffer for the current frame, growing it if `data`
+    /// doesn't fit, and returns the buffer to draw from this frame. Every frame in flight has
+    /// its own copy, so draw commands using it have to be pointed at the returned buffer each
+    /// frame. Replaced buffers are freed once no frame in flight can read them.
+    pub fn write_dynamic_buffer<T: bytemuck::Pod>(
+        &mut self,
+        id: DynamicBufferId,
+        data: &[T],
+    ) -> Result<vk::Buffer> {
+        let device = &self.device_info.device;
+
+        // The copy of this frame is free once the frame that last used it is done.
+        self.graphics_timeline
+            .wait(device, self.frames[self.current_frame].submitted)?;
+
+        let (buffer, replaced) = self.dynamic_buffers[id.0].write(
+            device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            self.current_frame,
+            bytemuck::cast_slice(data),
+        )?;
+
+        if let Some(replaced) = replaced {
+            self.retired_buffers
+                .push(self.graphics_timeline.submitted(), replaced);
+        }
+
+        Ok(buffer)
+    }
+
+    /// The buffer of a dynamic buffer the current frame draws from, if it was written for it.
+    pub fn dynamic_buffer(&self, id: DynamicBufferId) -> Option<vk::Buffer> {
+        self.dynamic_buffers[id.0].buffer(self.current_frame)
+    }
+
+    /// Uploads `data` to a buffer compute shaders can read and write, and that can be drawn
+    /// from as a vertex or index buffer. It is freed when the engine shuts down.
+    pub fn create_storage_buffer<T: bytemuck::Pod>(&mut self, data: &[T]) -> Result<vk::Buffer> {
+        let buffer = create_storage_buffer(
+            bytemuck::cast_slice(data),
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            &self.device_info.device,
+            &mut self.uploads,
+            "Storage",
+        )?;
+
+        let handle = buffer.buffer;
+        self.buffers
+            .as_mut()
+            .expect("Failed to get buffers")
+            .push(buffer);
+        Ok(handle)
+    }
+
+    /// Creates an image compute shaders can write, in the `GENERAL` layout, and returns its view.
+    /// It is freed when the engine shuts down.
+    pub fn create_storage_image(
+        &mut self,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<vk::ImageView> {
+        let image = create_storage_image(
+            &self.device_info.device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            extent,
+            format,
+            &self.uploads.transfer().queue_families,
+        )?;
+
+        let view = image.view;
+        let prepared = prepare_storage_image(
+            &self.device_info.device,
+            image.image,
+            self.command_pool,
+            self.device_info.queue,
+            &mut self.graphics_timeline,
+        );
+        self.storage_images.push(image);
+        prepared?;
+
+        Ok(view)
+    }
+
+    /// Queues a dispatch for the next frame. It runs on the graphics queue before the frame's
+    /// draws, which see everything it wrote.
+    pub fn dispatch(&mut self, command: DispatchCommand) {
+        self.dispatches.push(command);
+    }
+
+    /// Runs a dispatch right away and waits for it to finish. It uses the dedicated compute
+    /// queue when the device has one, so frames in flight keep rendering meanwhile; resources it
+    /// writes must not be in use by them.
+    pub fn run_compute(&mut self, command: &DispatchCommand) -> Result<()> {
+        let compute = self
+            .compute_pipelines
+            .iter()
+            .find(|compute| compute.pipeline == command.pipeline)
+            .expect("Failed to find compute pipeline");
+
+        let (command_pool, queue, timeline) = match (
+            self.compute_command_pool,
+            self.device_info.compute_queue,
+            self.compute_timeline.as_mut(),
+        ) {
+            (Some(command_pool), Some(queue), Some(timeline)) => (command_pool, queue, timeline),
+            _ => (
+                self.command_pool,
+                self.device_info.queue,
+                &mut self.graphics_timeline,
             ),
-            proj: cgmath::perspective(
+        };
+
+        let device = &self.device_info.device;
+
+        // Nothing waits for the transfer queue on this submission, so the dispatch may read
+        // buffers that are still being uploaded.
+        self.uploads.finish(
+            device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+        )?;
+
+        submit_once(device, command_pool, queue, timeline, |command_buffer| {
+            record_dispatch(device, command_buffer, compute, command)
+        })
+    }
+
+    /// Stops the event loop and releases all Vulkan resources after the current event.
+    pub fn exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    fn animate(&mut self) {
+        let spin = cgmath::Matrix4::from_axis_angle(
+            cgmath::Vector3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            cgmath::Deg(self.total_delta / 10000.0),
+        );
+        // Meshes the game added keep their transforms.
+        for draw in self
+            .draw_list
+            .commands_mut()
+            .iter_mut()
+            .filter(|draw| draw.vertex_buffer == self.default_mesh)
+        {
+            draw.transform = spin;
+        }
+
+        if self.controller.is_none() {
+            self.camera.proj = cgmath::perspective(
                 cgmath::Deg(45.0),
-                self.swapchain_info.extent.width as f32 / self.swapchain_info.extent.height as f32,
+                self.swapchain.extent.width as f32 / self.swapchain.extent.height as f32,
                 0.1,
                 100.0,
-            ),
-        };
+            );
+        }
+    }
 
-        let buffers = self.buffers.as_ref().expect("Failed to get buffers");
+    fn update(&mut self) {
+        let now = std::time::Instant::now();
+        let delta = now - self.last_frame;
+        self.last_frame = now;
 
-        let buffers = buffers
-            .iter()
-            .filter(|buffer| buffer.buffer_type == vk::BufferUsageFlags::UNIFORM_BUFFER)
-            .collect::<Vec<&Buffer>>();
+        if let Some(controller) = &mut self.controller {
+            controller.update(delta);
+            self.camera = controller.camera(self.swapchain.extent);
+        }
+
+        match self.callbacks.update.take() {
+            Some(mut update) => {
+                update(self, delta);
+                self.callbacks.update = Some(update);
+            }
+            None => self.animate(),
+        }
+    }
 
-        let allocation = &buffers[current_image]
+    /// Copies the camera into the uniform buffer of the current frame, which the GPU must be
+    /// done with.
+    fn write_camera(&self) {
+        let camera = self.camera;
+
+        let allocation = self.frames[self.current_frame]
+            .uniform_buffer
             .allocation
             .as_ref()
-            .expect("Failed to get allocation at index");
+            .expect("Failed to get allocation");
 
         unsafe {
             std::ptr::copy_nonoverlapping(
-                bytemuck::cast_slice(&[camera]).as_ptr() as *const u8,
+                bytemuck::cast_slice(&[camera]).as_ptr(),
                 allocation
                     .mapped_ptr()
                     .expect("Memory is not host visible")
@@ -382,110 +877,122 @@ impl App {
     fn cleanup(&mut self) {
         self.is_exiting = true;
 
-        unsafe { self.device_info.device.device_wait_idle() }
-            .expect("Failed to wait for device idle");
-
-        unsafe {
-            self.device_info
-                .device
-                .queue_wait_idle(self.device_info.queue)
+        if let Err(e) = unsafe { self.device_info.device.device_wait_idle() } {
+            error!("Failed to wait for device idle: {e}");
         }
-        .expect("Failed to wait for queue idle");
 
         let mut allocator = self.allocator.take().expect("Failed to get allocator");
 
-        for mut buffer in self.buffers.take().unwrap() {
-            let allocation = buffer.allocation.take().unwrap();
-            unsafe { self.device_info.device.destroy_buffer(buffer.buffer, None) };
-            allocator
-                .free(allocation)
-                .expect("Failed to free allocation");
+        if let Err(e) = self
+            .uploads
+            .destroy(&self.device_info.device, &mut allocator)
+        {
+            error!("Failed to free staging ring: {e}");
         }
 
-        drop(allocator);
-
-        for semaphore in &self.sync_info.render_semaphores {
-            unsafe { self.device_info.device.destroy_semaphore(*semaphore, None) }
+        for buffer in self.buffers.take().unwrap_or_default() {
+            debug!("Freeing {} buffer", buffer.name);
+            let name = buffer.name.clone();
+            if let Err(e) = destroy_buffer(&self.device_info.device, &mut allocator, buffer) {
+                error!("Failed to free {name} allocation: {e}");
+            }
         }
 
-        for semaphore in &self.sync_info.image_semaphores {
-            unsafe { self.device_info.device.destroy_semaphore(*semaphore, None) }
-        }
+        self.swapchain
+            .destroy(&self.device_info.device, &mut allocator);
 
-        for fence in &self.sync_info.frame_fences {
-            unsafe { self.device_info.device.destroy_fence(*fence, None) }
+        for mut swapchain in self.retired_swapchains.drain() {
+            swapchain.destroy(&self.device_info.device, &mut allocator);
         }
 
-        unsafe {
-            self.device_info
-                .device
-                .destroy_command_pool(self.command_info.command_pool, None)
+        for storage_image in self.storage_images.drain(..) {
+            if let Err(e) = destroy_image(&self.device_info.device, &mut allocator, storage_image) {
+                error!("Failed to free storage image: {e}");
+            }
         }
 
-        unsafe {
-            self.device_info.device.destroy_descriptor_set_layout(
-                self.descriptor_set_layouts[self.current_frame],
-                None,
-            )
+        if let Some(texture) = self.texture.take() {
+            if let Err(e) = destroy_texture(&self.device_info.device, &mut allocator, texture) {
+                error!("Failed to free texture: {e}");
+            }
         }
 
-        unsafe {
-            self.device_info
-                .device
-                .destroy_descriptor_pool(self.descriptor_pool, None)
-        };
+        for dynamic_buffer in self.dynamic_buffers.drain(..) {
+            dynamic_buffer.destroy(&self.device_info.device, &mut allocator);
+        }
 
-        for framebuffer in &self.framebuffers {
-            unsafe {
-                self.device_info
-                    .device
-                    .destroy_framebuffer(*framebuffer, None)
+        for buffer in self.retired_buffers.drain() {
+            if let Err(e) = destroy_buffer(&self.device_info.device, &mut allocator, buffer) {
+                error!("Failed to free retired buffer: {e}");
             }
         }
 
-        for view in &self.swapchain_info.swapchain_views {
-            unsafe { self.device_info.device.destroy_image_view(*view, None) }
+        for frame in self.frames.drain(..) {
+            frame.destroy(&self.device_info.device, &mut allocator);
+        }
+
+        self.graphics_timeline.destroy(&self.device_info.device);
+        if let Some(timeline) = &mut self.compute_timeline {
+            timeline.destroy(&self.device_info.device);
         }
 
+        drop(allocator);
+
         unsafe {
             self.device_info
                 .device
-                .destroy_render_pass(self.pipeline_info.render_pass, None)
+                .destroy_command_pool(self.command_pool, None)
         }
 
         unsafe {
             self.device_info
                 .device
-                .destroy_pipeline_layout(self.pipeline_info.pipeline_layout, None)
+                .destroy_command_pool(self.uploads.transfer().command_pool, None)
         }
 
-        unsafe {
-            for shader_module in self.pipeline_info.shader_modules {
+        if let Some(command_pool) = self.compute_command_pool {
+            unsafe {
                 self.device_info
                     .device
-                    .destroy_shader_module(shader_module, None)
+                    .destroy_command_pool(command_pool, None)
             }
         }
 
         unsafe {
-            self.device_info.device.destroy_pipeline(
+            self.device_info.device.destroy_descriptor_set_layout(
                 *self
-                    .pipeline_info
-                    .pipeline
+                    .descriptor_set_layouts
                     .first()
-                    .expect("Failed to find first pipeline"),
+                    .expect("Failed to get descriptor set layout"),
                 None,
             )
         }
 
-        for swapchain in &self.swapchain_info.swapchains {
-            unsafe {
-                self.swapchain_info
-                    .loader
-                    .destroy_swapchain(*swapchain, None)
-            };
+        unsafe {
+            self.device_info
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None)
+        };
+
+        destroy_pipeline(&self.device_info.device, &self.pipeline_info);
+
+        for compute in &self.compute_pipelines {
+            destroy_compute_pipeline(&self.device_info.device, compute);
+        }
+
+        if let Some(path) = &self.pipeline_cache_path {
+            if let Err(e) = save_pipeline_cache(&self.device_info.device, self.pipeline_cache, path)
+            {
+                error!("Failed to save pipeline cache: {e}");
+            }
         }
 
+        unsafe {
+            self.device_info
+                .device
+                .destroy_pipeline_cache(self.pipeline_cache, None)
+        };
+
         unsafe {
             self.surface_info
                 .surface_loader
@@ -503,156 +1010,383 @@ impl App {
         unsafe { self.instance.destroy_instance(None) };
     }
 
-    fn handle_input(&self, event: Option<winit::event::VirtualKeyCode>) {
-        if event.is_none() {
-            return;
+    /// Replaces the swapchain with one matching the current surface. The old one is handed
+    /// over to the new one and destroyed once the frames in flight are done with it. While the
+    /// window is minimized nothing is created and the swapchain stays dirty.
+    fn recreate_swapchain(&mut self) -> Result<()> {
+        self.swapchain_dirty = true;
+
+        let (surface_format, output) =
+            Swapchain::choose_format(&self.device_info, &self.surface_info, &self.color_outputs)?;
+        let format_changed =
+            surface_format.format != self.swapchain.format || output != self.swapchain.output;
+
+        let color_outputs = self.color_outputs.clone();
+        let current = self.swapchain.swapchain;
+
+        let result = if format_changed {
+            // Handing the current swapchain over retires it even if the new one fails, so the
+            // new format is tried next to it. Surfaces that only take one swapchain at a time
+            // need the handover.
+            match self.replace_swapchain(&color_outputs, vk::SwapchainKHR::null()) {
+                Err(VkcrError::Vulkan(vk::Result::ERROR_NATIVE_WINDOW_IN_USE_KHR)) => {
+                    self.replace_swapchain(&color_outputs, current)
+                }
+                result => result,
+            }
+        } else {
+            self.replace_swapchain(&color_outputs, current)
+        };
+
+        match result {
+            // The current format is known to work, so presenting goes on in it.
+            Err(e) if format_changed => {
+                error!("Keeping {:?} output: {e}", self.swapchain.output);
+                self.replace_swapchain(&[self.swapchain.output], current)
+            }
+            result => result,
         }
-        match event.expect("Failed to read input") {
-            winit::event::VirtualKeyCode::A => {}
-            winit::event::VirtualKeyCode::S => {}
-            _ => (),
+    }
+
+    /// Creates a swapchain in the first of `color_outputs` the surface supports, handing
+    /// `old_swapchain` over to it, and replaces the current one with it. A different format
+    /// comes with new pipelines. On failure, whatever was created is destroyed again.
+    fn replace_swapchain(
+        &mut self,
+        color_outputs: &[ColorOutput],
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<()> {
+        let allocator = self.allocator.as_mut().expect("Failed to get allocator");
+
+        let Some(mut swapchain) = Swapchain::create(
+            &self.device_info,
+            &self.surface_info,
+            &self.instance,
+            window_extent(&self.window),
+            color_outputs,
+            self.present_mode,
+            self.image_count,
+            old_swapchain,
+        )?
+        else {
+            return Ok(());
+        };
+
+        // The render pass and the shaders' output depend on the format, so a display switching
+        // to or from HDR needs new pipelines, which come with their own framebuffers.
+        let format_changed =
+            swapchain.format != self.swapchain.format || swapchain.output != self.swapchain.output;
+
+        if !format_changed {
+            if let Err(e) = swapchain.attach(
+                &self.device_info.device,
+                allocator,
+                self.pipeline_info.samples,
+                self.pipeline_info.render_pass,
+            ) {
+                swapchain.destroy(&self.device_info.device, allocator);
+                return Err(e);
+            }
+        } else if let Err(e) = self.attach_with_pipeline(&mut swapchain) {
+            let allocator = self.allocator.as_mut().expect("Failed to get allocator");
+            swapchain.destroy(&self.device_info.device, allocator);
+            return Err(e);
         }
+
+        let old = std::mem::replace(&mut self.swapchain, swapchain);
+        self.retired_swapchains
+            .push(self.graphics_timeline.submitted(), old);
+        self.send_hdr_metadata();
+
+        self.swapchain_dirty = false;
+
+        Ok(())
     }
 
-    fn resize(&mut self) {
-        unsafe { self.device_info.device.device_wait_idle() }.expect("Failed to wait for idle");
-        for view in &self.swapchain_info.swapchain_views {
-            unsafe { self.device_info.device.destroy_image_view(*view, None) }
+    /// Describes the content to HDR displays, when presenting in HDR.
+    fn send_hdr_metadata(&self) {
+        if let Some(hdr_metadata_fn) = &self.hdr_metadata_fn {
+            if self.swapchain.output.is_hdr() {
+                set_hdr_metadata(
+                    &self.device_info.device,
+                    hdr_metadata_fn,
+                    self.swapchain.swapchain,
+                    &self.hdr_metadata,
+                );
+            }
         }
-        self.swapchain_info = create_swapchain(
-            self.device_info.clone(),
-            self.surface_info.clone(),
-            &self.instance,
-            &self.window,
-            Some(self.swapchain_info.swapchains.clone()),
-        );
-        for _ in &self.swapchain_info.swapchains.clone() {
-            if self.swapchain_info.swapchains.len() > 1 {
-                unsafe {
-                    self.swapchain_info
-                        .loader
-                        .destroy_swapchain(self.swapchain_info.swapchains[0], None)
-                };
-
-                self.swapchain_info.swapchains.remove(0);
+    }
+
+    /// Recompiles the watched shaders and swaps in a pipeline built from them. On failure the
+    /// current pipeline stays in use and the error is logged.
+    fn reload_shaders(&mut self) {
+        if let Some(watcher) = &mut self.shader_watcher {
+            if let Err(e) = watcher.compile() {
+                error!("Keeping the previous pipeline: {e}");
+                return;
             }
         }
 
-        for framebuffer in &self.framebuffers {
+        match self.rebuild_pipeline() {
+            Ok(()) => info!("Reloaded {}", self.pipeline.shader),
+            Err(e) => error!("Keeping the previous pipeline: {e}"),
+        }
+    }
+
+    /// Gives `swapchain`, whose format differs from the current one, its attachments and
+    /// framebuffers along with pipelines for its format, which replace the current ones. Nothing
+    /// is replaced if any step fails.
+    fn attach_with_pipeline(&mut self, swapchain: &mut Swapchain) -> Result<()> {
+        swapchain.create_attachments(
+            &self.device_info.device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+            self.pipeline_info.samples,
+        )?;
+
+        let pipeline_info = self.build_pipelines(swapchain)?;
+
+        let framebuffers = swapchain
+            .create_framebuffers(&self.device_info.device, pipeline_info.render_pass)
+            .and_then(|framebuffers| {
+                unsafe { self.device_info.device.device_wait_idle() }?;
+                Ok(framebuffers)
+            });
+        match framebuffers {
+            Ok(framebuffers) => swapchain.framebuffers = framebuffers,
+            Err(e) => {
+                destroy_pipeline(&self.device_info.device, &pipeline_info);
+                return Err(e);
+            }
+        }
+
+        info!("Presenting in {:?}", swapchain.output);
+        self.replace_pipeline(pipeline_info);
+
+        Ok(())
+    }
+
+    fn rebuild_pipeline(&mut self) -> Result<()> {
+        let pipeline_info = self.build_pipelines(&self.swapchain)?;
+
+        if let Err(e) = unsafe { self.device_info.device.device_wait_idle() } {
+            destroy_pipeline(&self.device_info.device, &pipeline_info);
+            return Err(e.into());
+        }
+
+        let framebuffers = match self
+            .swapchain
+            .create_framebuffers(&self.device_info.device, pipeline_info.render_pass)
+        {
+            Ok(framebuffers) => framebuffers,
+            Err(e) => {
+                destroy_pipeline(&self.device_info.device, &pipeline_info);
+                return Err(e);
+            }
+        };
+
+        for framebuffer in std::mem::replace(&mut self.swapchain.framebuffers, framebuffers) {
             unsafe {
                 self.device_info
                     .device
-                    .destroy_framebuffer(*framebuffer, None)
+                    .destroy_framebuffer(framebuffer, None)
             }
         }
 
-        self.framebuffers = create_framebuffers(
-            self.swapchain_info.clone(),
-            self.pipeline_info.clone(),
-            &self.device_info.device,
-        );
+        self.replace_pipeline(pipeline_info);
+
+        Ok(())
     }
 
-    fn render(&mut self) {
+    /// Builds the main and material pipelines for images of `swapchain`.
+    fn build_pipelines(&self, swapchain: &Swapchain) -> Result<PipelineInfo> {
+        let mut pipeline_info = self.pipeline.build(
+            &self.device_info,
+            self.pipeline_cache,
+            &swapchain.extent,
+            swapchain.format,
+            swapchain.output,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            &self.descriptor_set_layouts,
+        )?;
+
+        // The descriptor sets were made for the old bindings and are shared by every pipeline.
+        if pipeline_info.layout.bindings != self.pipeline_info.layout.bindings {
+            destroy_pipeline(&self.device_info.device, &pipeline_info);
+            return Err(VkcrError::Shader {
+                path: self.pipeline.shader.clone(),
+                message: "descriptor bindings changed, restart to apply them".to_owned(),
+            });
+        }
+
+        // Materials keep their index in `pipeline`, so draws are remapped below like the main one.
+        for material in &self.materials {
+            match material.build_variant(
+                &self.device_info,
+                self.pipeline_cache,
+                &swapchain.extent,
+                &pipeline_info,
+            ) {
+                Ok(pipeline) => pipeline_info.pipeline.push(pipeline),
+                Err(e) => {
+                    destroy_pipeline(&self.device_info.device, &pipeline_info);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(pipeline_info)
+    }
+
+    /// Swaps in `pipeline_info` and points the draws at its pipelines. The GPU must be done
+    /// with the current ones.
+    fn replace_pipeline(&mut self, pipeline_info: PipelineInfo) {
+        let old = std::mem::replace(&mut self.pipeline_info, pipeline_info);
+
+        for draw in self.draw_list.commands_mut() {
+            if let Some(index) = old.pipeline.iter().position(|p| *p == draw.pipeline) {
+                draw.pipeline = self.pipeline_info.pipeline[index];
+            }
+        }
+
+        destroy_pipeline(&self.device_info.device, &old);
+    }
+
+    fn render(&mut self) -> Result<()> {
         if self.is_exiting {
-            return;
+            return Ok(());
+        }
+
+        if self.swapchain_dirty {
+            // Tried again next frame.
+            if let Err(e) = self.recreate_swapchain() {
+                error!("Failed to recreate swapchain: {e}");
+            }
+            // Minimized, so there is nothing to draw to until the window is restored.
+            if self.swapchain_dirty {
+                return Ok(());
+            }
         }
 
         let start = std::time::Instant::now();
 
-        self.update(self.current_frame);
+        self.update();
 
-        unsafe {
-            self.device_info.device.wait_for_fences(
-                &[self.sync_info.frame_fences[self.current_frame]],
-                true,
-                500000000,
-            )
+        self.graphics_timeline.wait(
+            &self.device_info.device,
+            self.frames[self.current_frame].submitted,
+        )?;
+
+        self.write_camera();
+
+        if self
+            .shader_watcher
+            .as_mut()
+            .is_some_and(ShaderWatcher::poll)
+        {
+            self.reload_shaders();
         }
-        .expect("Failed to wait for fences");
 
-        // self.check_for_shader_modification();
+        self.uploads.poll(
+            &self.device_info.device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+        )?;
+
+        let completed = self.graphics_timeline.completed(&self.device_info.device)?;
+        let allocator = self.allocator.as_mut().expect("Failed to get allocator");
+
+        for buffer in self.retired_buffers.collect(completed) {
+            destroy_buffer(&self.device_info.device, allocator, buffer)?;
+        }
+
+        for mut swapchain in self.retired_swapchains.collect(completed) {
+            swapchain.destroy(&self.device_info.device, allocator);
+        }
+
+        if let Some(mut render) = self.callbacks.render.take() {
+            render(self, self.current_frame);
+            self.callbacks.render = Some(render);
+        }
 
         let result = unsafe {
-            self.swapchain_info.loader.acquire_next_image(
-                *self
-                    .swapchain_info
-                    .swapchains
-                    .last()
-                    .expect("Failed to get last swapchain"),
+            self.swapchain.loader.acquire_next_image(
+                self.swapchain.swapchain,
                 500000000,
-                self.sync_info.image_semaphores[self.current_frame],
+                self.frames[self.current_frame].image_available,
                 vk::Fence::null(),
             )
         };
 
-        if result == Err(vk::Result::ERROR_OUT_OF_DATE_KHR) {
-            self.resize();
-            return;
-        }
-
+        // A suboptimal swapchain can still be presented to, so it is replaced after this frame.
         let index = match result {
-            Ok(index) => index.0,
-            Err(_) => return,
+            Ok((index, suboptimal)) => {
+                self.swapchain_dirty |= suboptimal;
+                index
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_dirty = true;
+                return Ok(());
+            }
+            Err(vk::Result::TIMEOUT | vk::Result::NOT_READY) => return Ok(()),
+            Err(e) => return Err(e.into()),
         };
 
+        // With more frames in flight than swapchain images, the acquired image can still be
+        // rendered to by an earlier frame.
+        self.graphics_timeline.wait(
+            &self.device_info.device,
+            self.swapchain.image_values[index as usize],
+        )?;
+
+        let frame = &self.frames[self.current_frame];
+
         unsafe {
             self.device_info
                 .device
-                .reset_fences(&[self.sync_info.frame_fences[self.current_frame]])
-        }
-        .expect("Failed reset fences");
-
-        unsafe {
-            self.device_info.device.reset_command_buffer(
-                self.command_info.command_buffers[self.current_frame],
-                vk::CommandBufferResetFlags::empty(),
-            )
-        }
-        .expect("Failed to reset command buffer");
+                .reset_command_buffer(frame.command_buffer, vk::CommandBufferResetFlags::empty())
+        }?;
 
         record_buffer(
-            index
-                .try_into()
-                .expect("Failed to convert index to usize from u32"),
-            self.pipeline_info.clone(),
-            self.swapchain_info.clone(),
-            self.framebuffers.clone(),
+            &self.pipeline_info,
+            self.swapchain.framebuffers[index as usize],
+            self.swapchain.extent,
             &self.device_info.device,
-            self.command_info.command_buffers[self.current_frame],
-            self.buffers.as_ref().unwrap(),
-            QUAD_INDICES
-                .len()
-                .try_into()
-                .expect("Failed to convert to u32"),
-            &self.descriptor_sets,
-            self.current_frame,
-        );
-
-        let signal_semaphores = [self.sync_info.render_semaphores[self.current_frame]];
-        let command_buffers = [self.command_info.command_buffers[self.current_frame]];
-        let wait_semaphores = [self.sync_info.image_semaphores[self.current_frame]];
-
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
-
-        unsafe {
-            self.device_info.device.queue_submit(
-                self.device_info.queue,
-                &[*submit_info],
-                self.sync_info.frame_fences[self.current_frame],
-            )
+            frame.command_buffer,
+            self.draw_list.commands(),
+            &self.compute_pipelines,
+            &self.dispatches,
+            Some(frame.descriptor_set),
+        )?;
+
+        self.dispatches.clear();
+
+        let mut wait_semaphores = vec![(
+            frame.image_available,
+            0,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )];
+
+        // Uploads queued up to now, including from the render callback, run on the transfer
+        // queue while the CPU goes on; the frame waits for them on the GPU.
+        if let Some((semaphore, value)) = self.uploads.frame_wait(
+            &self.device_info.device,
+            self.allocator.as_mut().expect("Failed to get allocator"),
+        )? {
+            wait_semaphores.push((semaphore, value, vk::PipelineStageFlags::ALL_COMMANDS));
         }
-        .expect("Failed to submit queue");
 
-        let swapchains = [*self
-            .swapchain_info
-            .swapchains
-            .last()
-            .expect("Failed to get last swapchain")];
+        let signal_semaphores = [self.swapchain.render_finished[index as usize]];
+
+        let submitted = self.graphics_timeline.submit(
+            &self.device_info.device,
+            self.device_info.queue,
+            &[frame.command_buffer],
+            &wait_semaphores,
+            &signal_semaphores,
+        )?;
+        self.frames[self.current_frame].submitted = submitted;
+        self.swapchain.image_values[index as usize] = submitted;
+
+        let swapchains = [self.swapchain.swapchain];
         let indices = [index];
 
         let present_info = vk::PresentInfoKHR::builder()
@@ -661,20 +1395,32 @@ impl App {
             .image_indices(&indices);
 
         let result = unsafe {
-            self.swapchain_info
+            self.swapchain
                 .loader
-                .queue_present(self.device_info.queue, &present_info)
+                .queue_present(self.device_info.present_queue, &present_info)
         };
 
-        if result.err() == Some(vk::Result::ERROR_OUT_OF_DATE_KHR) {
-            self.resize();
-            return;
+        // The frame was submitted either way, so it still counts as in flight.
+        match result {
+            Ok(suboptimal) => self.swapchain_dirty |= suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_dirty = true,
+            Err(e) => return Err(e.into()),
         }
 
-        self.current_frame = (self.current_frame + 1_usize) % MAX_CONCURRENT_FRAMES as usize;
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
         let current = std::time::Instant::now();
         let delta = (current - start).as_micros() as f32;
         let delta = if delta == 0.0 { 0.1 } else { delta };
         self.total_delta += delta;
+
+        Ok(())
+    }
+}
+
+fn window_extent(window: &winit::window::Window) -> vk::Extent2D {
+    let size = window.inner_size();
+    vk::Extent2D {
+        width: size.width,
+        height: size.height,
     }
 }
@@ -1,10 +1,12 @@
-use std::{mem::size_of_val, ptr};
-
 use ash::vk;
 
 use gpu_allocator::vulkan;
 
-use super::app::MAX_CONCURRENT_FRAMES;
+use super::device::DeviceInfo;
+use super::error::{Result, VkcrError};
+use super::reflect::ShaderLayout;
+use super::textures::Texture;
+use super::upload::{UploadHandle, UploadManager};
 
 pub struct Buffer {
     pub name: String,
@@ -13,22 +15,45 @@ pub struct Buffer {
     pub allocation: Option<gpu_allocator::vulkan::Allocation>,
 }
 
+pub fn create_allocator(
+    instance: &ash::Instance,
+    device_info: &DeviceInfo,
+) -> Result<vulkan::Allocator> {
+    Ok(vulkan::Allocator::new(&vulkan::AllocatorCreateDesc {
+        instance: instance.clone(),
+        device: device_info.device.clone(),
+        physical_device: device_info
+            .logical_devices
+            .first()
+            .expect("Failed to get first logical device")
+            .physical_device,
+        debug_settings: Default::default(),
+        buffer_device_address: false,
+    })?)
+}
+
 pub fn create_buffer(
     device: &ash::Device,
     allocator: &mut vulkan::Allocator,
     size: u64,
     name: &str,
-    sharing_mode: vk::SharingMode,
+    queue_families: &[u32],
     usage: vk::BufferUsageFlags,
     location: gpu_allocator::MemoryLocation,
-) -> (vk::Buffer, vulkan::Allocation) {
+) -> Result<(vk::Buffer, vulkan::Allocation)> {
+    let sharing_mode = if queue_families.len() > 1 {
+        vk::SharingMode::CONCURRENT
+    } else {
+        vk::SharingMode::EXCLUSIVE
+    };
+
     let buffer_info = vk::BufferCreateInfo::builder()
         .size(size)
         .usage(usage)
-        .sharing_mode(sharing_mode);
+        .sharing_mode(sharing_mode)
+        .queue_family_indices(queue_families);
 
-    let buffer = unsafe { device.create_buffer(&buffer_info, None) }
-        .unwrap_or_else(|_| panic!("Failed to create {name}"));
+    let buffer = unsafe { device.create_buffer(&buffer_info, None) }?;
 
     let memory_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
 
@@ -38,271 +63,302 @@ pub fn create_buffer(
             requirements: memory_requirements,
             location,
             linear: true,
-        })
-        .expect("Failed to allocate");
+        })?;
 
-    unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()) }
-        .expect("Failed to bind memory");
+    unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()) }?;
 
-    (buffer, allocation)
+    Ok((buffer, allocation))
 }
 
 pub fn create_vertex_buffer<T: bytemuck::Pod>(
-    vertices: T,
+    vertices: &[T],
     allocator: &mut gpu_allocator::vulkan::Allocator,
     device: &ash::Device,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
-) -> Buffer {
-    create_buffer_staging(
-        vertices,
+    uploads: &mut UploadManager,
+) -> Result<Buffer> {
+    create_buffer_staging_from_bytes(
+        bytemuck::cast_slice(vertices),
         allocator,
         device,
-        command_pool,
-        queue,
+        uploads,
         vk::BufferUsageFlags::VERTEX_BUFFER,
         "Vertex",
         gpu_allocator::MemoryLocation::GpuOnly,
     )
 }
 
-pub fn create_index_buffer<T: bytemuck::Pod>(
-    indices: T,
+pub fn create_index_buffer(
+    indices: &[u32],
     allocator: &mut gpu_allocator::vulkan::Allocator,
     device: &ash::Device,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
-) -> Buffer {
-    create_buffer_staging(
-        indices,
+    uploads: &mut UploadManager,
+) -> Result<Buffer> {
+    create_buffer_staging_from_bytes(
+        bytemuck::cast_slice(indices),
         allocator,
         device,
-        command_pool,
-        queue,
+        uploads,
         vk::BufferUsageFlags::INDEX_BUFFER,
         "Index",
         gpu_allocator::MemoryLocation::GpuOnly,
     )
 }
 
+/// Creates `count` host-visible uniform buffers holding `uniform_data`, one for each frame in
+/// flight. They are written through their mapping, so nothing goes through the transfer queue.
 pub fn create_uniform_buffers<T: bytemuck::Pod>(
     uniform_data: T,
     allocator: &mut gpu_allocator::vulkan::Allocator,
     device: &ash::Device,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
-) -> Vec<Buffer> {
-    let mut allocations = Vec::new();
-    let mut buffers = Vec::new();
-    let mut uniform_buffers = Vec::new();
-    for i in 0..MAX_CONCURRENT_FRAMES {
-        let buffer = create_buffer_staging(
-            uniform_data,
-            allocator,
-            device,
-            command_pool,
-            queue,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-            "Uniform",
-            gpu_allocator::MemoryLocation::GpuToCpu,
-        );
-        uniform_buffers.push(buffer.buffer);
-        allocations.push(buffer.allocation);
-        buffers.push(Buffer {
-            name: "Uniform".to_owned(),
-            buffer: uniform_buffers[i as usize],
-            buffer_type: vk::BufferUsageFlags::UNIFORM_BUFFER,
-            allocation: allocations[i as usize].take(),
-        });
-    }
-
-    buffers
+    count: usize,
+) -> Result<Vec<Buffer>> {
+    let data = bytemuck::bytes_of(&uniform_data);
+
+    (0..count)
+        .map(|_| {
+            let (buffer, mut allocation) = create_buffer(
+                device,
+                allocator,
+                data.len() as u64,
+                "Uniform Buffer",
+                &[],
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                gpu_allocator::MemoryLocation::GpuToCpu,
+            )?;
+
+            allocation
+                .mapped_slice_mut()
+                .expect("Memory is not host visible")[..data.len()]
+                .copy_from_slice(data);
+
+            Ok(Buffer {
+                name: "Uniform".to_owned(),
+                buffer,
+                buffer_type: vk::BufferUsageFlags::UNIFORM_BUFFER,
+                allocation: Some(allocation),
+            })
+        })
+        .collect()
 }
 
-fn copy_buffer(
+/// Like [`create_buffer_staging_from_bytes`], for a slice of plain data.
+#[allow(clippy::too_many_arguments)]
+pub fn create_buffer_staging_from_slice<T: bytemuck::Pod>(
+    data: &[T],
+    allocator: &mut gpu_allocator::vulkan::Allocator,
     device: &ash::Device,
-    src: vk::Buffer,
-    dst: vk::Buffer,
-    size: u64,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
-) {
-    let allocate_info = vk::CommandBufferAllocateInfo::builder()
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_pool(command_pool)
-        .command_buffer_count(1);
-
-    let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info) }
-        .expect("Failed to allocate command buffer");
-
-    let begin_info =
-        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-    unsafe {
-        device.begin_command_buffer(
-            *command_buffer
-                .first()
-                .expect("Failed to get command buffer"),
-            &begin_info,
-        )
-    }
-    .expect("Failed to begin command buffer");
-
-    let copy_region = vk::BufferCopy::builder().size(size).build();
-
-    unsafe {
-        device.cmd_copy_buffer(
-            *command_buffer
-                .first()
-                .expect("Failed to get command buffer"),
-            dst,
-            src,
-            &[copy_region],
-        )
-    }
-
-    unsafe {
-        device.end_command_buffer(
-            *command_buffer
-                .first()
-                .expect("Failed to get command buffer"),
-        )
-    }
-    .expect("Failed to begin command buffer");
-
-    let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffer);
-    unsafe { device.queue_submit(queue, &[*submit_info], vk::Fence::null()) }
-        .expect("Failed to begin command buffer");
+    uploads: &mut UploadManager,
+    usage: vk::BufferUsageFlags,
+    name: &str,
+    location: gpu_allocator::MemoryLocation,
+) -> Result<Buffer> {
+    create_buffer_staging_from_bytes(
+        bytemuck::cast_slice(data),
+        allocator,
+        device,
+        uploads,
+        usage,
+        name,
+        location,
+    )
+}
 
-    unsafe { device.queue_wait_idle(queue) }.expect("Failed to wait for queue idle");
+/// Creates a buffer and queues the upload of `data` to it, without waiting for the copy. Frames
+/// wait for pending uploads before they run; anything else has to wait on `uploads` first.
+#[allow(clippy::too_many_arguments)]
+pub fn create_buffer_staging_from_bytes(
+    data: &[u8],
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+    usage: vk::BufferUsageFlags,
+    name: &str,
+    location: gpu_allocator::MemoryLocation,
+) -> Result<Buffer> {
+    let (buffer, _) = upload_buffer(data, allocator, device, uploads, usage, name, location)?;
 
-    unsafe { device.free_command_buffers(command_pool, &command_buffer) };
+    Ok(buffer)
 }
 
-pub fn create_buffer_staging<T: bytemuck::Pod>(
-    data: T,
+/// Like [`create_buffer_staging_from_bytes`], but also returns the handle `uploads` reports
+/// resident once the copy is done.
+#[allow(clippy::too_many_arguments)]
+pub fn upload_buffer(
+    data: &[u8],
     allocator: &mut gpu_allocator::vulkan::Allocator,
     device: &ash::Device,
-    command_pool: vk::CommandPool,
-    queue: vk::Queue,
+    uploads: &mut UploadManager,
     usage: vk::BufferUsageFlags,
     name: &str,
     location: gpu_allocator::MemoryLocation,
-) -> Buffer {
-    let (staging_buffer, staging_allocation) = create_buffer(
-        device,
-        allocator,
-        size_of_val(&data) as u64,
-        "Staging Buffer",
-        vk::SharingMode::EXCLUSIVE,
-        usage | vk::BufferUsageFlags::TRANSFER_SRC,
-        gpu_allocator::MemoryLocation::CpuToGpu,
-    );
-
-    unsafe {
-        ptr::copy_nonoverlapping(
-            bytemuck::cast_slice(&[data]).as_ptr() as *const u8,
-            staging_allocation
-                .mapped_ptr()
-                .expect("Failed to get pointer")
-                .as_ptr() as *mut u8,
-            size_of_val(&data),
-        )
-    };
+) -> Result<(Buffer, UploadHandle)> {
+    // Zero-sized buffers are invalid.
+    if data.is_empty() {
+        return Err(VkcrError::EmptyUpload {
+            name: format!("{name} Buffer"),
+        });
+    }
 
     let (buffer, allocation) = create_buffer(
         device,
         allocator,
-        size_of_val(&data) as u64,
+        data.len() as u64,
         format!("{name} Buffer").as_str(),
-        vk::SharingMode::EXCLUSIVE,
+        &uploads.transfer().queue_families,
         usage | vk::BufferUsageFlags::TRANSFER_DST,
         location,
-    );
+    )?;
+
+    let upload = uploads.upload(device, allocator, data, buffer, 0)?;
+
+    Ok((
+        Buffer {
+            name: name.to_owned(),
+            buffer,
+            buffer_type: usage,
+            allocation: Some(allocation),
+        },
+        upload,
+    ))
+}
 
-    copy_buffer(
+/// Creates a GPU-only buffer filled with `data` that compute shaders read and write. It can also
+/// be bound as a vertex, index or indirect buffer, to draw what a compute shader produced.
+pub fn create_storage_buffer(
+    data: &[u8],
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+    name: &str,
+) -> Result<Buffer> {
+    create_buffer_staging_from_bytes(
+        data,
+        allocator,
         device,
-        buffer,
-        staging_buffer,
-        size_of_val(&data) as u64,
-        command_pool,
-        queue,
-    );
-
-    unsafe { device.destroy_buffer(staging_buffer, None) };
-
-    allocator
-        .free(staging_allocation)
-        .expect("Failed to free staging allocation");
-
-    Buffer {
-        name: name.to_owned(),
-        buffer,
-        buffer_type: usage,
-        allocation: Some(allocation),
-    }
+        uploads,
+        vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::VERTEX_BUFFER
+            | vk::BufferUsageFlags::INDEX_BUFFER
+            | vk::BufferUsageFlags::INDIRECT_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_SRC,
+        name,
+        gpu_allocator::MemoryLocation::GpuOnly,
+    )
 }
 
+/// Creates set 0 of `shader_layout` once for each of `uniform_buffers`, which are bound to its
+/// uniform buffers. Combined image samplers are bound to `texture`; the shaders may not use any
+/// other kind of descriptor or set.
 pub fn create_descriptor_sets<T: bytemuck::Pod>(
     device: &ash::Device,
+    shader_layout: &ShaderLayout,
     uniform_buffers: &[Buffer],
     data_type: T,
-) -> (Vec<vk::DescriptorSet>, vk::DescriptorPool, Vec<vk::DescriptorSetLayout>) {
-    let ubo_layout_binding = vk::DescriptorSetLayoutBinding::builder()
-        .binding(0)
-        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::VERTEX);
-
-    let binding = [*ubo_layout_binding];
+    texture: &Texture,
+) -> Result<(
+    Vec<vk::DescriptorSet>,
+    vk::DescriptorPool,
+    Vec<vk::DescriptorSetLayout>,
+)> {
+    for binding in &shader_layout.bindings {
+        let supported = matches!(
+            binding.descriptor_type,
+            vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        );
+        if binding.set != 0 || !supported {
+            return Err(VkcrError::Shader {
+                path: shader_layout.name.clone(),
+                message: format!(
+                    "set {} binding {} is a {:?}, but only uniform buffers and combined image \
+                     samplers in set 0 are supported",
+                    binding.set, binding.binding, binding.descriptor_type
+                ),
+            });
+        }
+    }
+
+    let binding = shader_layout.set_layout_bindings(0);
     let descriptor_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&binding);
 
-    let layout = unsafe { device.create_descriptor_set_layout(&descriptor_create_info, None) }
-        .expect("Failed to create descriptor set layout");
+    let layout = unsafe { device.create_descriptor_set_layout(&descriptor_create_info, None) }?;
 
-    let descriptor_pool_size =
-        vk::DescriptorPoolSize::builder().descriptor_count(MAX_CONCURRENT_FRAMES as u32)
-        .ty(vk::DescriptorType::UNIFORM_BUFFER);
+    let descriptor_pool_sizes: Vec<vk::DescriptorPoolSize> = binding
+        .iter()
+        .map(|binding| {
+            *vk::DescriptorPoolSize::builder()
+                .descriptor_count(binding.descriptor_count * uniform_buffers.len() as u32)
+                .ty(binding.descriptor_type)
+        })
+        .collect();
 
-    let descriptor_pool_sizes = [*descriptor_pool_size];
     let pool_info = vk::DescriptorPoolCreateInfo::builder()
         .pool_sizes(&descriptor_pool_sizes)
-        .max_sets(MAX_CONCURRENT_FRAMES as u32);
+        .max_sets(uniform_buffers.len() as u32);
 
-    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None) }
-        .expect("Failed to create descriptor pool");
+    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None) }?;
 
-    let layouts = [layout; MAX_CONCURRENT_FRAMES as usize];
+    let layouts = vec![layout; uniform_buffers.len()];
     let allocate_info = vk::DescriptorSetAllocateInfo::builder()
         .descriptor_pool(descriptor_pool)
         .set_layouts(&layouts);
 
-    let descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
-        .expect("Failed to allocate descriptor sets");
-    
+    let descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }?;
+
+    // Array bindings get the same resource in every element.
+    let max_count = binding
+        .iter()
+        .map(|binding| binding.descriptor_count as usize)
+        .max()
+        .unwrap_or(0);
 
     for (i, descriptor_set) in descriptor_sets.iter().enumerate() {
         let buffer_info = vk::DescriptorBufferInfo::builder()
-            .buffer(
-                uniform_buffers
-                    .iter()
-                    .map(|buffer| buffer.buffer)
-                    .collect::<Vec<vk::Buffer>>()[i],
-            )
+            .buffer(uniform_buffers[i].buffer)
             .offset(0)
             .range(std::mem::size_of_val(&data_type) as u64);
 
-        let buffer_infos = &[*buffer_info];
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.image.view)
+            .sampler(texture.sampler);
+
+        let buffer_infos = vec![*buffer_info; max_count];
+        let image_infos = vec![*image_info; max_count];
+
+        let writes: Vec<vk::WriteDescriptorSet> = binding
+            .iter()
+            .map(|binding| {
+                let count = binding.descriptor_count as usize;
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(binding.binding)
+                    .dst_array_element(0)
+                    .descriptor_type(binding.descriptor_type);
+
+                if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+                    *write.buffer_info(&buffer_infos[..count])
+                } else {
+                    *write.image_info(&image_infos[..count])
+                }
+            })
+            .collect();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) }
+    }
+
+    Ok((descriptor_sets, descriptor_pool, layouts))
+}
 
-        let descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(*descriptor_set)
-            .dst_binding(0)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .buffer_info(buffer_infos);
+pub fn destroy_buffer(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    mut buffer: Buffer,
+) -> Result<()> {
+    unsafe { device.destroy_buffer(buffer.buffer, None) };
 
-        unsafe { device.update_descriptor_sets(&[*descriptor_write], &[]) }
+    if let Some(allocation) = buffer.allocation.take() {
+        allocator.free(allocation)?;
     }
 
-    (descriptor_sets, descriptor_pool, layouts.to_vec())
+    Ok(())
 }
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use super::app::{App, DEFAULT_FRAMES_IN_FLIGHT};
+use super::camera::Camera;
+use super::controller::CameraMode;
+use super::device::DeviceRequirements;
+use super::error::Result;
+use super::mesh::Mesh;
+use super::pipeline::{DepthState, PipelineBuilder};
+use super::swapchain::{ColorOutput, HdrMetadata, PresentMode};
+use super::vertex::Vertex;
+
+pub type UpdateCallback = Box<dyn FnMut(&mut App, Duration)>;
+pub type InputCallback = Box<dyn FnMut(&mut App, &winit::event::WindowEvent)>;
+pub type RenderCallback = Box<dyn FnMut(&mut App, usize)>;
+
+#[derive(Clone)]
+pub struct EngineConfig {
+    pub title: String,
+    pub min_width: u32,
+    pub min_height: u32,
+    /// Shaders and fixed-function state of the pipeline the mesh is drawn with.
+    pub pipeline: PipelineBuilder,
+    /// Recompiles `<shader>.vert`/`<shader>.frag` and their includes when they change on disk and
+    /// rebuilds the pipeline. On by default in debug builds.
+    pub hot_reload: bool,
+    /// PNG or JPEG sampled by the default shader. Without one a white texture is bound.
+    pub texture: Option<String>,
+    pub mesh: Mesh,
+    pub camera: Camera,
+    /// Drives the camera from keyboard and mouse input when set.
+    pub camera_mode: Option<CameraMode>,
+    /// Directory the pipeline cache is loaded from at startup and saved to on shutdown. Without
+    /// one, pipelines are compiled from scratch on every run.
+    pub cache_dir: Option<String>,
+    /// Extensions, features and limits the GPU must have. The swapchain extension is always
+    /// required on top.
+    pub device: DeviceRequirements,
+    /// Can be changed while running with [`App::set_present_mode`].
+    pub present_mode: PresentMode,
+    /// Swapchain images to request. Without a count, one more than the surface's minimum.
+    pub image_count: Option<u32>,
+    /// Frames the CPU may record ahead of the GPU. Each has its own command buffer, uniform
+    /// buffer and descriptor set.
+    pub frames_in_flight: usize,
+    /// Color spaces to present in, best first. sRGB is used when the display supports none of
+    /// them.
+    pub color_outputs: Vec<ColorOutput>,
+    /// Sent to the display when presenting in HDR and `VK_EXT_hdr_metadata` is available.
+    pub hdr_metadata: HdrMetadata,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            title: "VKCR".to_owned(),
+            min_width: 300,
+            min_height: 300,
+            pipeline: PipelineBuilder::default(),
+            hot_reload: cfg!(debug_assertions),
+            texture: None,
+            mesh: Mesh::default(),
+            camera: Camera {
+                view: cgmath::Matrix4::look_at_rh(
+                    cgmath::Point3 {
+                        x: 0.0,
+                        y: 1.0,
+                        z: 2.0,
+                    },
+                    cgmath::Point3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    cgmath::Vector3 {
+                        x: 0.0,
+                        y: 1.0,
+                        z: 0.0,
+                    },
+                ),
+                proj: cgmath::perspective(cgmath::Deg(45.0), 1.0, 0.1, 100.0),
+            },
+            camera_mode: None,
+            cache_dir: Some("cache".to_owned()),
+            device: DeviceRequirements::default(),
+            present_mode: PresentMode::default(),
+            image_count: None,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            color_outputs: vec![ColorOutput::Srgb],
+            hdr_metadata: HdrMetadata::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Callbacks {
+    pub update: Option<UpdateCallback>,
+    pub input: Option<InputCallback>,
+    pub render: Option<RenderCallback>,
+}
+
+/// Configures and starts the engine from a game crate.
+///
+/// Without an update callback the engine spins the configured mesh; once one is set,
+/// the game owns the camera through [`App::camera_mut`].
+#[derive(Default)]
+pub struct AppBuilder {
+    config: EngineConfig,
+    callbacks: Callbacks,
+}
+
+impl AppBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.config.title = title.to_owned();
+        self
+    }
+
+    pub fn min_size(mut self, width: u32, height: u32) -> Self {
+        self.config.min_width = width;
+        self.config.min_height = height;
+        self
+    }
+
+    /// Path of the shader pair without the `_v.spv`/`_f.spv` suffix.
+    pub fn shader(mut self, shader: &str) -> Self {
+        self.config.pipeline.shader = shader.to_owned();
+        self
+    }
+
+    pub fn hot_reload(mut self, enabled: bool) -> Self {
+        self.config.hot_reload = enabled;
+        self
+    }
+
+    pub fn texture(mut self, path: &str) -> Self {
+        self.config.texture = Some(path.to_owned());
+        self
+    }
+
+    pub fn geometry(mut self, vertices: &[Vertex], indices: &[u32]) -> Self {
+        self.config.mesh = Mesh {
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+        };
+        self
+    }
+
+    /// Draws a mesh loaded with [`Mesh::load`] instead of the default quad.
+    pub fn mesh(mut self, mesh: Mesh) -> Self {
+        self.config.mesh = mesh;
+        self
+    }
+
+    pub fn camera(mut self, camera: Camera) -> Self {
+        self.config.camera = camera;
+        self
+    }
+
+    pub fn camera_controller(mut self, mode: CameraMode) -> Self {
+        self.config.camera_mode = Some(mode);
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.config.pipeline.depth = depth;
+        self
+    }
+
+    /// Replaces the whole pipeline state, including the shader set with [`AppBuilder::shader`].
+    pub fn pipeline(mut self, pipeline: PipelineBuilder) -> Self {
+        self.config.pipeline = pipeline;
+        self
+    }
+
+    /// Directory for files the engine keeps between runs, like the pipeline cache. `None`
+    /// disables them.
+    pub fn cache_dir(mut self, dir: Option<&str>) -> Self {
+        self.config.cache_dir = dir.map(str::to_owned);
+        self
+    }
+
+    /// Restricts which GPU the engine runs on. `VKCR_DEVICE` still overrides the device choice.
+    pub fn device(mut self, requirements: DeviceRequirements) -> Self {
+        self.config.device = requirements;
+        self
+    }
+
+    pub fn present_mode(mut self, mode: PresentMode) -> Self {
+        self.config.present_mode = mode;
+        self
+    }
+
+    /// Swapchain images to request, clamped to what the surface supports.
+    pub fn image_count(mut self, count: u32) -> Self {
+        self.config.image_count = Some(count);
+        self
+    }
+
+    /// Frames recorded ahead of the GPU, at least one. More smooth out uneven frame times at
+    /// the cost of latency.
+    pub fn frames_in_flight(mut self, count: usize) -> Self {
+        self.config.frames_in_flight = count.max(1);
+        self
+    }
+
+    /// Color spaces the shaders can output, best first. The one in use is passed to fragment
+    /// shaders as a specialization constant and reported by [`App::color_output`].
+    pub fn color_outputs(mut self, outputs: &[ColorOutput]) -> Self {
+        self.config.color_outputs = outputs.to_vec();
+        self
+    }
+
+    pub fn hdr_metadata(mut self, metadata: HdrMetadata) -> Self {
+        self.config.hdr_metadata = metadata;
+        self
+    }
+
+    /// Called once per frame with the time since the previous frame.
+    pub fn on_update(mut self, callback: impl FnMut(&mut App, Duration) + 'static) -> Self {
+        self.callbacks.update = Some(Box::new(callback));
+        self
+    }
+
+    /// Called for every event the window receives, before the engine handles it.
+    pub fn on_input(
+        mut self,
+        callback: impl FnMut(&mut App, &winit::event::WindowEvent) + 'static,
+    ) -> Self {
+        self.callbacks.input = Some(Box::new(callback));
+        self
+    }
+
+    /// Called once per frame after the update, right before the frame's commands are recorded,
+    /// with the index of the frame in flight.
+    pub fn on_render(mut self, callback: impl FnMut(&mut App, usize) + 'static) -> Self {
+        self.callbacks.render = Some(Box::new(callback));
+        self
+    }
+
+    /// Opens the window and runs the event loop. Only returns if the engine fails to start.
+    pub fn run(self) -> Result<()> {
+        App::run_with(self.config, self.callbacks)
+    }
+}
@@ -1,8 +1,11 @@
 use ash::vk::{self, Offset2D};
 
 use super::{
-    app::MAX_CONCURRENT_FRAMES, buffers::Buffer, device::QueueFamily, pipeline::PipelineInfo,
-    swapchain::SwapchainInfo,
+    compute::{record_dispatches, ComputePipeline, DispatchCommand},
+    device::{DeviceInfo, QueueFamily},
+    draw::DrawCommand,
+    error::Result,
+    pipeline::{PipelineInfo, TRANSFORM_SIZE},
 };
 
 #[derive(Clone)]
@@ -11,71 +14,116 @@ pub struct CommandInfo {
     pub command_buffers: Vec<vk::CommandBuffer>,
 }
 
-pub fn create_command_pool(queue_family: &QueueFamily, device: &ash::Device) -> CommandInfo {
+/// Where uploads are recorded and submitted. What they fill is shared between
+/// `queue_families`, so other queues can use it without an ownership transfer.
+#[derive(Clone)]
+pub struct TransferQueue {
+    pub command_pool: vk::CommandPool,
+    pub queue: vk::Queue,
+    pub queue_families: Vec<u32>,
+}
+
+/// Uploads through the device's transfer queue, which is a dedicated one when the device has
+/// a transfer-only family.
+pub fn create_transfer_queue(device_info: &DeviceInfo) -> Result<TransferQueue> {
+    let command_pool_info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(device_info.transfer_family);
+
+    let command_pool = unsafe {
+        device_info
+            .device
+            .create_command_pool(&command_pool_info, None)
+    }?;
+
+    Ok(TransferQueue {
+        command_pool,
+        queue: device_info.transfer_queue,
+        queue_families: device_info
+            .queue_families
+            .iter()
+            .map(|family| family.index)
+            .collect(),
+    })
+}
+
+/// Creates a pool on `queue_family` with `buffer_count` resettable command buffers.
+pub fn create_command_pool(
+    queue_family: &QueueFamily,
+    device: &ash::Device,
+    buffer_count: u32,
+) -> Result<CommandInfo> {
     let command_pool_info = vk::CommandPoolCreateInfo::builder()
         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(queue_family.index);
 
-    let command_pool = unsafe { device.create_command_pool(&command_pool_info, None) }
-        .expect("Failed to create command pool");
+    let command_pool = unsafe { device.create_command_pool(&command_pool_info, None) }?;
 
     let buffer_info = vk::CommandBufferAllocateInfo::builder()
         .command_pool(command_pool)
         .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(MAX_CONCURRENT_FRAMES.into());
+        .command_buffer_count(buffer_count);
 
-    let mut command_buffers = unsafe { device.allocate_command_buffers(&buffer_info) }
-        .expect("Failed to allocate command buffers");
+    let command_buffers = unsafe { device.allocate_command_buffers(&buffer_info) }?;
 
-    command_buffers.reserve((MAX_CONCURRENT_FRAMES - 1).into());
-
-    CommandInfo {
+    Ok(CommandInfo {
         command_pool,
         command_buffers,
-    }
+    })
 }
 
+/// Records `dispatches` followed by the render pass drawing `draws`, with `descriptor_set`
+/// bound as set 0 if there is one.
+#[allow(clippy::too_many_arguments)]
 pub fn record_buffer(
-    index: usize,
-    pipeline_info: PipelineInfo,
-    swapchain_info: SwapchainInfo,
-    framebuffers: Vec<vk::Framebuffer>,
+    pipeline_info: &PipelineInfo,
+    framebuffer: vk::Framebuffer,
+    extent: vk::Extent2D,
     device: &ash::Device,
     command_buffer: vk::CommandBuffer,
-    buffers: &[Buffer],
-    count: u32,
-    descriptor_sets: &[vk::DescriptorSet],
-    current_frame: usize,
-) {
+    draws: &[DrawCommand],
+    compute_pipelines: &[ComputePipeline],
+    dispatches: &[DispatchCommand],
+    descriptor_set: Option<vk::DescriptorSet>,
+) -> Result<()> {
     let buffer_begin_info = vk::CommandBufferBeginInfo::builder();
-    unsafe { device.begin_command_buffer(command_buffer, &buffer_begin_info) }
-        .expect("Failed to record commands");
+    unsafe { device.begin_command_buffer(command_buffer, &buffer_begin_info) }?;
+
+    record_dispatches(device, command_buffer, compute_pipelines, dispatches);
 
     let render_area = vk::Rect2D::builder()
-        .extent(swapchain_info.extent)
+        .extent(extent)
         .offset(*Offset2D::builder().x(0).y(0));
 
     let render_pass_info = vk::RenderPassBeginInfo::builder()
         .render_pass(pipeline_info.render_pass)
-        .clear_values(&[vk::ClearValue {
-            color: vk::ClearColorValue {
-                int32: [0, 0, 0, 1],
+        .clear_values(&[
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    int32: [0, 0, 0, 1],
+                },
             },
-        }])
-        .framebuffer(framebuffers[index])
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ])
+        .framebuffer(framebuffer)
         .render_area(*render_area);
 
     let viewport = vk::Viewport::builder()
         .x(0.0)
         .y(0.0)
-        .width(swapchain_info.extent.width as f32)
-        .height(swapchain_info.extent.height as f32)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
         .min_depth(0.0)
         .max_depth(1.0);
 
     let scissor = vk::Rect2D::builder()
         .offset(*vk::Offset2D::builder().x(0).y(0))
-        .extent(swapchain_info.extent);
+        .extent(extent);
 
     unsafe {
         device.cmd_begin_render_pass(
@@ -85,82 +133,68 @@ pub fn record_buffer(
         )
     };
 
-    unsafe {
-        device.cmd_bind_pipeline(
-            command_buffer,
-            vk::PipelineBindPoint::GRAPHICS,
-            *pipeline_info
-                .pipeline
-                .first()
-                .expect("Failed to get pipeline"),
-        )
-    };
-
-    unsafe {
-        device.cmd_bind_vertex_buffers(
-            command_buffer,
-            0,
-            &buffers
-                .iter()
-                .filter(|buffer| buffer.buffer_type == vk::BufferUsageFlags::VERTEX_BUFFER)
-                .map(|buffer| buffer.buffer)
-                .collect::<Vec<vk::Buffer>>(),
-            &[0],
-        )
-    }
-
-    let index_buffers = buffers
-        .iter()
-        .filter(|buffer| buffer.buffer_type == vk::BufferUsageFlags::INDEX_BUFFER)
-        .map(|buffer| buffer.buffer)
-        .collect::<Vec<vk::Buffer>>();
-
-    let use_index_buffer = index_buffers.first().is_some();
-
-    let uniform_buffers = buffers
-        .iter()
-        .filter(|buffer| buffer.buffer_type == vk::BufferUsageFlags::UNIFORM_BUFFER)
-        .map(|buffer| buffer.buffer)
-        .collect::<Vec<vk::Buffer>>();
-
-    let use_uniform_buffer = uniform_buffers.first().is_some();
-
-    if use_index_buffer {
-        unsafe {
-            device.cmd_bind_index_buffer(
-                command_buffer,
-                *index_buffers
-                    .first()
-                    .expect("Failed to get first index buffer"),
-                0,
-                vk::IndexType::UINT16,
-            )
-        }
-    }
-
     unsafe { device.cmd_set_viewport(command_buffer, 0, &[*viewport]) }
     unsafe { device.cmd_set_scissor(command_buffer, 0, &[*scissor]) };
 
-    if use_uniform_buffer {
+    if let Some(descriptor_set) = descriptor_set {
         unsafe {
             device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 pipeline_info.pipeline_layout,
                 0,
-                &[descriptor_sets[current_frame]],
+                &[descriptor_set],
                 &[],
             )
         }
     }
 
-    if use_index_buffer {
-        unsafe { device.cmd_draw_indexed(command_buffer, count, 1, 0, 0, 0) };
-    } else {
-        unsafe { device.cmd_draw(command_buffer, count, 0, 0, 0) };
+    let mut bound_pipeline = vk::Pipeline::null();
+
+    // Shaders without a push constant block for the model matrix just don't get it.
+    let transform_stages = pipeline_info.layout.push_constant_stages(0, TRANSFORM_SIZE);
+
+    for draw in draws {
+        if draw.pipeline != bound_pipeline {
+            unsafe {
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    draw.pipeline,
+                )
+            };
+            bound_pipeline = draw.pipeline;
+        }
+
+        let transform: &[f32; 16] = draw.transform.as_ref();
+
+        if !transform_stages.is_empty() {
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    pipeline_info.pipeline_layout,
+                    transform_stages,
+                    0,
+                    bytemuck::cast_slice(transform),
+                )
+            };
+        }
+
+        unsafe { device.cmd_bind_vertex_buffers(command_buffer, 0, &[draw.vertex_buffer], &[0]) }
+
+        if draw.index_buffer != vk::Buffer::null() {
+            unsafe {
+                device.cmd_bind_index_buffer(command_buffer, draw.index_buffer, 0, draw.index_type)
+            };
+            unsafe { device.cmd_draw_indexed(command_buffer, draw.index_count, 1, 0, 0, 0) };
+        } else {
+            unsafe { device.cmd_draw(command_buffer, draw.index_count, 1, 0, 0) };
+        }
     }
 
     unsafe { device.cmd_end_render_pass(command_buffer) };
 
-    unsafe { device.end_command_buffer(command_buffer) }.expect("Failed to end command buffer");
+    unsafe { device.end_command_buffer(command_buffer) }?;
+
+    Ok(())
 }
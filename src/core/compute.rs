@@ -0,0 +1,434 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use super::error::{Result, VkcrError};
+use super::pipeline::create_shader_pipeline;
+use super::reflect::ShaderLayout;
+use super::timeline::Timeline;
+
+/// Stages of the frame's graphics work that read what compute shaders write.
+const GRAPHICS_STAGES: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+    vk::PipelineStageFlags::DRAW_INDIRECT.as_raw()
+        | vk::PipelineStageFlags::VERTEX_INPUT.as_raw()
+        | vk::PipelineStageFlags::VERTEX_SHADER.as_raw()
+        | vk::PipelineStageFlags::FRAGMENT_SHADER.as_raw(),
+);
+
+/// A compute shader with its layout and one descriptor set per set it declares.
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub layout: ShaderLayout,
+    shader_module: vk::ShaderModule,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// A resource bound to a compute shader descriptor. Storage images are expected in the
+/// `GENERAL` layout, sampled images in `SHADER_READ_ONLY_OPTIMAL`.
+#[derive(Clone, Copy, Debug)]
+pub enum ComputeResource {
+    UniformBuffer(vk::Buffer),
+    StorageBuffer(vk::Buffer),
+    StorageImage(vk::ImageView),
+    SampledImage(vk::ImageView, vk::Sampler),
+}
+
+impl ComputeResource {
+    fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            ComputeResource::UniformBuffer(_) => vk::DescriptorType::UNIFORM_BUFFER,
+            ComputeResource::StorageBuffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+            ComputeResource::StorageImage(_) => vk::DescriptorType::STORAGE_IMAGE,
+            ComputeResource::SampledImage(..) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        }
+    }
+}
+
+/// One dispatch of a compute pipeline. `push_constants` are pushed at offset 0 when the shader
+/// declares a push constant block.
+#[derive(Clone, Debug)]
+pub struct DispatchCommand {
+    pub pipeline: vk::Pipeline,
+    pub group_count: [u32; 3],
+    pub push_constants: Vec<u8>,
+}
+
+impl DispatchCommand {
+    pub fn new(pipeline: vk::Pipeline, group_count: [u32; 3]) -> Self {
+        DispatchCommand {
+            pipeline,
+            group_count,
+            push_constants: Vec::new(),
+        }
+    }
+
+    pub fn with_push_constants<T: bytemuck::Pod>(mut self, data: &T) -> Self {
+        self.push_constants = bytemuck::bytes_of(data).to_vec();
+        self
+    }
+}
+
+/// Creates a pipeline from `<shader>_c.spv`, with descriptor set layouts and push constant
+/// ranges taken from the shader. Every binding starts out unbound; see [`ComputePipeline::bind`].
+pub(crate) fn create_compute_pipeline(
+    device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    shader: &str,
+) -> Result<ComputePipeline> {
+    let mut compute = ComputePipeline {
+        pipeline: vk::Pipeline::null(),
+        pipeline_layout: vk::PipelineLayout::null(),
+        layout: ShaderLayout::load_compute(shader)?,
+        shader_module: vk::ShaderModule::null(),
+        set_layouts: Vec::new(),
+        descriptor_pool: vk::DescriptorPool::null(),
+        descriptor_sets: Vec::new(),
+    };
+
+    // Handles not created yet are null, which Vulkan ignores on destruction.
+    if let Err(e) = create_compute_objects(device, pipeline_cache, shader, &mut compute) {
+        destroy_compute_pipeline(device, &compute);
+        return Err(e);
+    }
+
+    Ok(compute)
+}
+
+/// Fills in the Vulkan objects of `compute`, stopping at the first that fails.
+fn create_compute_objects(
+    device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    shader: &str,
+    compute: &mut ComputePipeline,
+) -> Result<()> {
+    let layout = &compute.layout;
+
+    let set_count = layout
+        .bindings
+        .iter()
+        .map(|binding| binding.set + 1)
+        .max()
+        .unwrap_or(0);
+
+    for set in 0..set_count {
+        let bindings = layout.set_layout_bindings(set);
+        let set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        compute
+            .set_layouts
+            .push(unsafe { device.create_descriptor_set_layout(&set_layout_create_info, None) }?);
+    }
+
+    let pool_sizes: Vec<vk::DescriptorPoolSize> = layout
+        .bindings
+        .iter()
+        .map(|binding| {
+            *vk::DescriptorPoolSize::builder()
+                .ty(binding.descriptor_type)
+                .descriptor_count(binding.count)
+        })
+        .collect();
+
+    if !compute.set_layouts.is_empty() {
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(set_count);
+        compute.descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None) }?;
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(compute.descriptor_pool)
+            .set_layouts(&compute.set_layouts);
+        compute.descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }?;
+    }
+
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(&compute.set_layouts)
+        .push_constant_ranges(&layout.push_constants);
+
+    compute.pipeline_layout =
+        unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None) }?;
+
+    compute.shader_module = create_shader_pipeline(device, &format!("{shader}_c.spv"))?;
+
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(compute.shader_module)
+        .name(CStr::from_bytes_with_nul("main\0".as_bytes()).expect("Failed to convert to cstr"));
+
+    let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(*stage)
+        .layout(compute.pipeline_layout);
+
+    let pipeline =
+        unsafe { device.create_compute_pipelines(pipeline_cache, &[*pipeline_create_info], None) }
+            .map_err(|(_, result)| result)?;
+    compute.pipeline = pipeline[0];
+
+    Ok(())
+}
+
+impl ComputePipeline {
+    /// Points `binding` of `set` at `resource`. The descriptor must not be in use by a
+    /// dispatch that is still executing.
+    pub(crate) fn bind(
+        &self,
+        device: &ash::Device,
+        set: u32,
+        binding: u32,
+        resource: ComputeResource,
+    ) -> Result<()> {
+        let declared = self
+            .layout
+            .bindings
+            .iter()
+            .find(|b| b.set == set && b.binding == binding)
+            .ok_or_else(|| VkcrError::Shader {
+                path: self.layout.name.clone(),
+                message: format!("set {set} binding {binding} is not declared"),
+            })?;
+
+        if declared.descriptor_type != resource.descriptor_type() {
+            return Err(VkcrError::Shader {
+                path: self.layout.name.clone(),
+                message: format!(
+                    "set {set} binding {binding} is a {:?}, not a {:?}",
+                    declared.descriptor_type,
+                    resource.descriptor_type()
+                ),
+            });
+        }
+
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_sets[set as usize])
+            .dst_binding(binding)
+            .dst_array_element(0)
+            .descriptor_type(declared.descriptor_type);
+
+        let buffer_info = |buffer| {
+            [*vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)]
+        };
+        let image_info = |view, sampler, layout| {
+            [*vk::DescriptorImageInfo::builder()
+                .image_view(view)
+                .sampler(sampler)
+                .image_layout(layout)]
+        };
+
+        match resource {
+            ComputeResource::UniformBuffer(buffer) | ComputeResource::StorageBuffer(buffer) => {
+                let info = buffer_info(buffer);
+                unsafe { device.update_descriptor_sets(&[*write.buffer_info(&info)], &[]) }
+            }
+            ComputeResource::StorageImage(view) => {
+                let info = image_info(view, vk::Sampler::null(), vk::ImageLayout::GENERAL);
+                unsafe { device.update_descriptor_sets(&[*write.image_info(&info)], &[]) }
+            }
+            ComputeResource::SampledImage(view, sampler) => {
+                let info = image_info(view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+                unsafe { device.update_descriptor_sets(&[*write.image_info(&info)], &[]) }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Records `dispatches` in order. The first one waits for earlier graphics work to stop reading
+/// its resources, and each one's writes are made visible to the following dispatches and to the
+/// vertex input, vertex and fragment shaders and indirect draws recorded after them.
+pub(crate) fn record_dispatches(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    pipelines: &[ComputePipeline],
+    dispatches: &[DispatchCommand],
+) {
+    if dispatches.is_empty() {
+        return;
+    }
+
+    // Only an execution dependency: the previous frame may still be reading what we overwrite.
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            GRAPHICS_STAGES,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[],
+        )
+    };
+
+    let written = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(
+            vk::AccessFlags::SHADER_READ
+                | vk::AccessFlags::SHADER_WRITE
+                | vk::AccessFlags::UNIFORM_READ
+                | vk::AccessFlags::INDIRECT_COMMAND_READ
+                | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                | vk::AccessFlags::INDEX_READ,
+        );
+
+    for dispatch in dispatches {
+        let Some(compute) = pipelines.iter().find(|p| p.pipeline == dispatch.pipeline) else {
+            error!("Skipping dispatch of unknown compute pipeline");
+            continue;
+        };
+
+        record_dispatch(device, command_buffer, compute, dispatch);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | GRAPHICS_STAGES,
+                vk::DependencyFlags::empty(),
+                &[*written],
+                &[],
+                &[],
+            )
+        };
+    }
+}
+
+pub(crate) fn record_dispatch(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    compute: &ComputePipeline,
+    dispatch: &DispatchCommand,
+) {
+    unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            compute.pipeline,
+        )
+    };
+
+    if !compute.descriptor_sets.is_empty() {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                compute.pipeline_layout,
+                0,
+                &compute.descriptor_sets,
+                &[],
+            )
+        }
+    }
+
+    let push_stages = compute
+        .layout
+        .push_constant_stages(0, dispatch.push_constants.len() as u32);
+
+    if !dispatch.push_constants.is_empty() && !push_stages.is_empty() {
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                compute.pipeline_layout,
+                push_stages,
+                0,
+                &dispatch.push_constants,
+            )
+        };
+    }
+
+    let [x, y, z] = dispatch.group_count;
+    unsafe { device.cmd_dispatch(command_buffer, x, y, z) };
+}
+
+/// Moves a freshly created storage image into the `GENERAL` layout compute shaders use.
+pub(crate) fn prepare_storage_image(
+    device: &ash::Device,
+    image: vk::Image,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    timeline: &mut Timeline,
+) -> Result<()> {
+    submit_once(device, command_pool, queue, timeline, |command_buffer| {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+
+        let to_general = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(*subresource_range);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[*to_general],
+            )
+        };
+    })
+}
+
+/// Records with `record` into a temporary command buffer, submits it to `queue` and waits on
+/// `timeline`, which tracks `queue`, for it to finish.
+pub(crate) fn submit_once(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    timeline: &mut Timeline,
+    record: impl FnOnce(vk::CommandBuffer),
+) -> Result<()> {
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(command_pool)
+        .command_buffer_count(1);
+
+    let command_buffers = unsafe { device.allocate_command_buffers(&allocate_info) }?;
+    let command_buffer = *command_buffers
+        .first()
+        .expect("Failed to get command buffer");
+
+    let begin_info =
+        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    let result = unsafe { device.begin_command_buffer(command_buffer, &begin_info) }
+        .and_then(|()| {
+            record(command_buffer);
+            unsafe { device.end_command_buffer(command_buffer) }
+        })
+        .map_err(VkcrError::from)
+        .and_then(|()| timeline.submit(device, queue, &command_buffers, &[], &[]))
+        .and_then(|value| timeline.wait(device, value));
+
+    unsafe { device.free_command_buffers(command_pool, &command_buffers) };
+
+    result
+}
+
+pub(crate) fn destroy_compute_pipeline(device: &ash::Device, compute: &ComputePipeline) {
+    unsafe { device.destroy_pipeline(compute.pipeline, None) };
+    unsafe { device.destroy_pipeline_layout(compute.pipeline_layout, None) };
+    unsafe { device.destroy_shader_module(compute.shader_module, None) };
+
+    if compute.descriptor_pool != vk::DescriptorPool::null() {
+        unsafe { device.destroy_descriptor_pool(compute.descriptor_pool, None) };
+    }
+
+    for set_layout in &compute.set_layouts {
+        unsafe { device.destroy_descriptor_set_layout(*set_layout, None) };
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use ash::vk;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use super::camera::Camera;
+
+const UP: Vector3<f32> = Vector3 {
+    x: 0.0,
+    y: 1.0,
+    z: 0.0,
+};
+
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// Pixels of scrolling that count as one wheel line on touchpads.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    /// WASD moves along the view direction, Space/E and Shift/Q move up and down, dragging with
+    /// the left mouse button looks around.
+    Fly,
+    /// Circles around `target`. Dragging or A/D rotate, W/S and the wheel zoom.
+    Orbit,
+    /// Looks down the negative Z axis with an orthographic projection. WASD or dragging pans,
+    /// the wheel zooms.
+    Ortho2D,
+}
+
+/// Turns winit keyboard and mouse events into a [`Camera`]. Feed it every window event with
+/// [`CameraController::handle_event`], advance it once per frame with
+/// [`CameraController::update`] and read the result with [`CameraController::camera`].
+#[derive(Clone, Debug)]
+pub struct CameraController {
+    pub mode: CameraMode,
+    /// Eye position in fly mode, center of the view in 2D mode.
+    pub position: Point3<f32>,
+    /// Point the orbit camera circles around.
+    pub target: Point3<f32>,
+    /// Distance between the orbit camera and its target.
+    pub distance: f32,
+    /// Radians around the Y axis; zero looks down the negative Z axis.
+    pub yaw: f32,
+    /// Radians above the horizon, clamped just short of straight up or down.
+    pub pitch: f32,
+    /// Half of the visible height in 2D mode, in world units.
+    pub zoom: f32,
+    pub fov: cgmath::Deg<f32>,
+    pub near: f32,
+    pub far: f32,
+    /// World units per second.
+    pub move_speed: f32,
+    /// Radians per second for keyboard rotation in orbit mode.
+    pub turn_speed: f32,
+    /// Radians per pixel of mouse movement when looking around. In 2D mode a one pixel drag
+    /// pans by this fraction of `zoom`.
+    pub look_sensitivity: f32,
+    pressed: HashSet<VirtualKeyCode>,
+    dragging: bool,
+    cursor: Option<(f64, f64)>,
+}
+
+impl CameraController {
+    /// Starts at the engine's default view, from (0, 1, 2) towards the origin. The 2D mode
+    /// starts centered on the origin.
+    pub fn new(mode: CameraMode) -> Self {
+        let eye = Vector3::new(0.0_f32, 1.0, 2.0);
+
+        CameraController {
+            mode,
+            position: match mode {
+                CameraMode::Ortho2D => Point3::new(0.0, 0.0, 0.0),
+                CameraMode::Fly | CameraMode::Orbit => Point3::from_vec(eye),
+            },
+            target: Point3::new(0.0, 0.0, 0.0),
+            distance: eye.magnitude(),
+            yaw: 0.0,
+            pitch: -(eye.y / eye.z).atan(),
+            zoom: 1.0,
+            fov: cgmath::Deg(45.0),
+            near: 0.1,
+            far: 100.0,
+            move_speed: 2.0,
+            turn_speed: 1.5,
+            look_sensitivity: 0.005,
+            pressed: HashSet::new(),
+            dragging: false,
+            cursor: None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.pressed.insert(key),
+                        ElementState::Released => self.pressed.remove(&key),
+                    };
+                }
+            }
+
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = *state == ElementState::Pressed;
+            }
+
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor {
+                    if self.dragging {
+                        self.drag((position.x - x) as f32, (position.y - y) as f32);
+                    }
+                }
+                self.cursor = Some((position.x, position.y));
+            }
+
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+            }
+
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.scroll(lines);
+            }
+
+            WindowEvent::Focused(false) => {
+                self.pressed.clear();
+                self.dragging = false;
+            }
+
+            _ => (),
+        }
+    }
+
+    /// Applies held keys for the time that passed since the previous frame.
+    pub fn update(&mut self, delta: Duration) {
+        let seconds = delta.as_secs_f32();
+        let axis = |positive: &[VirtualKeyCode], negative: &[VirtualKeyCode]| {
+            let held = |keys: &[VirtualKeyCode]| keys.iter().any(|key| self.pressed.contains(key));
+            f32::from(u8::from(held(positive))) - f32::from(u8::from(held(negative)))
+        };
+
+        let forward = axis(&[VirtualKeyCode::W], &[VirtualKeyCode::S]);
+        let right = axis(&[VirtualKeyCode::D], &[VirtualKeyCode::A]);
+        let up = axis(
+            &[VirtualKeyCode::Space, VirtualKeyCode::E],
+            &[VirtualKeyCode::LShift, VirtualKeyCode::Q],
+        );
+
+        match self.mode {
+            CameraMode::Fly => {
+                let movement = self.forward() * forward + self.right() * right + UP * up;
+                if movement.magnitude2() > 0.0 {
+                    self.position += movement.normalize() * self.move_speed * seconds;
+                }
+            }
+
+            CameraMode::Orbit => {
+                self.yaw -= right * self.turn_speed * seconds;
+                self.distance =
+                    (self.distance - forward * self.move_speed * seconds).max(self.near);
+            }
+
+            CameraMode::Ortho2D => {
+                self.position.x += right * self.move_speed * self.zoom * seconds;
+                self.position.y += forward * self.move_speed * self.zoom * seconds;
+            }
+        }
+    }
+
+    /// View and projection for the current state, with the aspect ratio taken from `extent`.
+    pub fn camera(&self, extent: vk::Extent2D) -> Camera {
+        let aspect = extent.width.max(1) as f32 / extent.height.max(1) as f32;
+
+        match self.mode {
+            CameraMode::Fly => Camera {
+                view: cgmath::Matrix4::look_to_rh(self.position, self.forward(), UP),
+                proj: cgmath::perspective(self.fov, aspect, self.near, self.far),
+            },
+
+            CameraMode::Orbit => Camera {
+                view: cgmath::Matrix4::look_at_rh(self.eye(), self.target, UP),
+                proj: cgmath::perspective(self.fov, aspect, self.near, self.far),
+            },
+
+            CameraMode::Ortho2D => Camera {
+                view: cgmath::Matrix4::look_to_rh(
+                    Point3::new(self.position.x, self.position.y, 1.0),
+                    -Vector3::unit_z(),
+                    UP,
+                ),
+                proj: cgmath::ortho(
+                    -self.zoom * aspect,
+                    self.zoom * aspect,
+                    -self.zoom,
+                    self.zoom,
+                    self.near,
+                    self.far,
+                ),
+            },
+        }
+    }
+
+    /// Where the camera currently is, in every mode.
+    pub fn eye(&self) -> Point3<f32> {
+        match self.mode {
+            CameraMode::Fly => self.position,
+            CameraMode::Orbit => self.target - self.forward() * self.distance,
+            CameraMode::Ortho2D => Point3::new(self.position.x, self.position.y, 1.0),
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    fn right(&self) -> Vector3<f32> {
+        self.forward().cross(UP).normalize()
+    }
+
+    fn drag(&mut self, dx: f32, dy: f32) {
+        match self.mode {
+            CameraMode::Fly | CameraMode::Orbit => {
+                self.yaw += dx * self.look_sensitivity;
+                self.pitch = (self.pitch - dy * self.look_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+
+            CameraMode::Ortho2D => {
+                let units_per_pixel = self.look_sensitivity * self.zoom;
+                self.position.x -= dx * units_per_pixel;
+                self.position.y += dy * units_per_pixel;
+            }
+        }
+    }
+
+    fn scroll(&mut self, lines: f32) {
+        let factor = 0.9_f32.powf(lines);
+
+        match self.mode {
+            CameraMode::Fly => (),
+            CameraMode::Orbit => self.distance = (self.distance * factor).max(self.near),
+            CameraMode::Ortho2D => self.zoom = (self.zoom * factor).max(f32::EPSILON),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use ash::vk;
 
+use super::error::Result;
 
 #[derive(Clone)]
 pub struct DebugInfo {
@@ -28,7 +29,7 @@ unsafe extern "system" fn debug_callback(
     vk::FALSE
 }
 
-pub fn create_debug(entry: &ash::Entry, instance: &ash::Instance) -> DebugInfo {
+pub fn create_debug(entry: &ash::Entry, instance: &ash::Instance) -> Result<DebugInfo> {
     let debug_utils = ash::extensions::ext::DebugUtils::new(entry, instance);
     let debug_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .pfn_user_callback(Some(debug_callback))
@@ -44,11 +45,11 @@ pub fn create_debug(entry: &ash::Entry, instance: &ash::Instance) -> DebugInfo {
                 | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
         );
 
-    let messenger = unsafe { debug_utils.create_debug_utils_messenger(&debug_create_info, None) }
-        .expect("Failed to create debug utils messenger");
+    let messenger =
+        unsafe { debug_utils.create_debug_utils_messenger(&debug_create_info, None) }?;
 
-    DebugInfo {
+    Ok(DebugInfo {
         loader: debug_utils,
         messenger,
-    }
+    })
 }
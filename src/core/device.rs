@@ -1,5 +1,13 @@
+use std::ffi::{CStr, CString};
+
 use ash::vk;
 
+use super::error::{Result, VkcrError};
+use super::surface::SurfaceInfo;
+
+/// Overrides the device choice by name or index, like [`DeviceRequirements::device`].
+pub const DEVICE_ENV: &str = "VKCR_DEVICE";
+
 #[derive(Debug, Clone)]
 pub struct LogicalDevice {
     pub physical_device: vk::PhysicalDevice,
@@ -9,10 +17,33 @@ pub struct LogicalDevice {
 
 #[derive(Clone)]
 pub struct DeviceInfo {
+    /// Every device that meets the requirements, best first. The first one is in use.
     pub logical_devices: Vec<LogicalDevice>,
     pub device: ash::Device,
+    /// Every family a queue was created on, starting with the graphics family.
     pub queue_families: Vec<QueueFamily>,
+    /// The graphics queue, which also runs the compute work of each frame.
     pub queue: vk::Queue,
+    pub graphics_family: u32,
+    /// Presents swapchain images. The same queue as `queue` unless the graphics family can't
+    /// present.
+    pub present_queue: vk::Queue,
+    pub present_family: u32,
+    /// Runs uploads, on a dedicated transfer family when the device has one so they don't
+    /// wait behind rendering.
+    pub transfer_queue: vk::Queue,
+    pub transfer_family: u32,
+    /// A queue of a compute-only family, for compute work that runs alongside rendering.
+    /// `None` when the device has no such family.
+    pub compute_queue: Option<vk::Queue>,
+    pub compute_family: Option<u32>,
+    /// The required and supported optional features that were turned on for `device`.
+    pub features: vk::PhysicalDeviceFeatures,
+    /// The required and supported optional extensions that were turned on for `device`.
+    pub extensions: Vec<String>,
+    /// Whether the Vulkan 1.2 timeline semaphore feature was turned on. Without it, queue
+    /// progress is tracked with fences instead.
+    pub timeline_semaphores: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -21,85 +52,525 @@ pub struct QueueFamily {
     pub index: u32,
 }
 
-pub fn create_device(instance: &ash::Instance) -> DeviceInfo {
-    let physical_devices = unsafe {
-        instance
-            .enumerate_physical_devices()
-            .expect("Failed to enumerate physical devices")
-    };
+/// A limit a device has to meet, like `|limits| limits.max_push_constants_size >= 256`.
+#[derive(Clone, Copy, Debug)]
+pub struct LimitRequirement {
+    /// Shown when a device is rejected for not meeting the limit.
+    pub name: &'static str,
+    pub check: fn(&vk::PhysicalDeviceLimits) -> bool,
+}
+
+/// What a physical device needs to support to be picked. Devices that don't are skipped and
+/// the reason is logged; among the rest discrete GPUs win over integrated, virtual and software
+/// ones, and more device-local memory wins within a type.
+///
+/// Optional extensions and features are turned on when the device supports them; check
+/// [`DeviceInfo::extensions`] and [`DeviceInfo::features`] for what was.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceRequirements {
+    pub extensions: Vec<String>,
+    pub optional_extensions: Vec<String>,
+    pub features: vk::PhysicalDeviceFeatures,
+    pub optional_features: vk::PhysicalDeviceFeatures,
+    pub limits: Vec<LimitRequirement>,
+    /// Only considers the device whose name contains this, ignoring case, or whose index in
+    /// enumeration order it is. The `VKCR_DEVICE` environment variable takes precedence, so
+    /// `VKCR_DEVICE=lavapipe` forces the software rasterizer.
+    pub device: Option<String>,
+}
+
+impl DeviceRequirements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extension(mut self, name: &str) -> Self {
+        self.extensions.push(name.to_owned());
+        self
+    }
+
+    pub fn optional_extension(mut self, name: &str) -> Self {
+        self.optional_extensions.push(name.to_owned());
+        self
+    }
+
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn optional_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.optional_features = features;
+        self
+    }
+
+    pub fn limit(
+        mut self,
+        name: &'static str,
+        check: fn(&vk::PhysicalDeviceLimits) -> bool,
+    ) -> Self {
+        self.limits.push(LimitRequirement { name, check });
+        self
+    }
+
+    pub fn device(mut self, selector: &str) -> Self {
+        self.device = Some(selector.to_owned());
+        self
+    }
+}
 
-    let mut logical_devices: Vec<LogicalDevice> = Vec::new();
-    let mut index = None;
-    for physical_device in physical_devices {
-        let families =
-            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+/// A device that met every requirement.
+struct Candidate {
+    logical_device: LogicalDevice,
+    graphics_family: u32,
+    present_family: u32,
+    transfer_family: u32,
+    compute_family: Option<u32>,
+    extensions: Vec<String>,
+    features: vk::PhysicalDeviceFeatures,
+    timeline_semaphores: bool,
+}
+
+/// Picks the best device meeting `requirements` that can present to `surface`, or any
+/// device when there is no surface, and creates a logical device with one queue per family
+/// the graphics, present, transfer and compute queues come from.
+pub fn create_device(
+    instance: &ash::Instance,
+    surface: Option<&SurfaceInfo>,
+    requirements: &DeviceRequirements,
+) -> Result<DeviceInfo> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices() }?;
+
+    let selector = std::env::var(DEVICE_ENV)
+        .ok()
+        .filter(|selector| !selector.is_empty())
+        .or_else(|| requirements.device.clone());
+
+    let mut candidates = Vec::new();
+    let mut rejected = Vec::new();
+    for (index, physical_device) in physical_devices.into_iter().enumerate() {
         let properties = unsafe { instance.get_physical_device_properties(physical_device) };
-        for (i, family) in families.iter().enumerate() {
-            if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                let mut priority = match properties.device_type {
-                    vk::PhysicalDeviceType::DISCRETE_GPU => 4,
-                    vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
-                    vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
-                    _ => 1,
-                };
-                priority *= properties.limits.max_image_dimension1_d;
-                logical_devices.push(LogicalDevice {
-                    physical_device,
-                    priority: priority.into(),
-                    properties,
-                });
-                index = Some(i);
+        let name = device_name(&properties);
+
+        let result = match &selector {
+            Some(selector) if !matches_selector(selector, index, &name) => {
+                Err(format!("does not match the selected device \"{selector}\""))
+            }
+            _ => evaluate(instance, physical_device, properties, surface, requirements),
+        };
+
+        match result {
+            Ok(candidate) => {
+                debug!("{}", candidate.logical_device);
+                candidates.push(candidate);
+            }
+            Err(reason) => {
+                info!("Skipping device {name}: {reason}");
+                rejected.push(format!("{name}: {reason}"));
             }
         }
     }
 
-    if logical_devices.is_empty() {
-        panic!("No devices that support Vulkan were found");
-    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.logical_device.priority));
 
-    for graphics_device in &logical_devices {
-        debug!("{}", graphics_device);
-    }
+    let Some(chosen) = candidates.first() else {
+        return Err(VkcrError::NoSuitableDevice { rejected });
+    };
 
-    let device_extensions: Vec<*const i8> = vec![ash::extensions::khr::Swapchain::name().as_ptr()];
+    info!("Using {}", chosen.logical_device);
 
-    let queue_families = vec![QueueFamily {
-        priorities: Box::new([1.0]),
-        index: index.expect("No devices that support Vulkan were found") as u32
-    }];
+    let mut queue_families: Vec<QueueFamily> = Vec::new();
+    for index in [
+        Some(chosen.graphics_family),
+        Some(chosen.present_family),
+        Some(chosen.transfer_family),
+        chosen.compute_family,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if queue_families.iter().all(|family| family.index != index) {
+            queue_families.push(QueueFamily {
+                priorities: Box::new([1.0]),
+                index,
+            });
+        }
+    }
 
-    let queue_create_info = vk::DeviceQueueCreateInfo::builder()
-        .flags(vk::DeviceQueueCreateFlags::empty())
-        .queue_family_index(queue_families[0].index)
-        .queue_priorities(&queue_families[0].priorities);
+    let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+        .iter()
+        .map(|family| {
+            *vk::DeviceQueueCreateInfo::builder()
+                .flags(vk::DeviceQueueCreateFlags::empty())
+                .queue_family_index(family.index)
+                .queue_priorities(&family.priorities)
+        })
+        .collect();
 
-    let queue_create_infos = [*queue_create_info];
+    let extension_names: Vec<CString> = chosen
+        .extensions
+        .iter()
+        .map(|name| CString::new(name.as_str()).expect("Failed to convert extension name"))
+        .collect();
+    let extension_pointers: Vec<*const i8> =
+        extension_names.iter().map(|name| name.as_ptr()).collect();
 
-    let device_create_info = vk::DeviceCreateInfo::builder()
-        .enabled_extension_names(&device_extensions)
-        .queue_create_infos(&queue_create_infos);
+    let mut timeline_features =
+        vk::PhysicalDeviceTimelineSemaphoreFeatures::builder().timeline_semaphore(true);
 
-    logical_devices.sort_by_key(|v| std::cmp::Reverse(v.priority));
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
+        .enabled_extension_names(&extension_pointers)
+        .queue_create_infos(&queue_create_infos)
+        .enabled_features(&chosen.features);
+    // Devices older than Vulkan 1.2 don't know the structure.
+    if chosen.timeline_semaphores {
+        device_create_info = device_create_info.push_next(&mut timeline_features);
+    }
 
     let device = unsafe {
         instance.create_device(
-            logical_devices[0].physical_device,
+            chosen.logical_device.physical_device,
             &device_create_info,
             None,
         )
-    }
-    .expect("Failed to create device");
+    }?;
+
+    let get_queue = |family| unsafe { device.get_device_queue(family, 0) };
+    let queue = get_queue(chosen.graphics_family);
+    let present_queue = get_queue(chosen.present_family);
+    let transfer_queue = get_queue(chosen.transfer_family);
+    let compute_queue = chosen.compute_family.map(get_queue);
 
-    let queue = unsafe { device.get_device_queue(queue_families[0].index, 0) };
+    let graphics_family = chosen.graphics_family;
+    let present_family = chosen.present_family;
+    let transfer_family = chosen.transfer_family;
+    let compute_family = chosen.compute_family;
+    let features = chosen.features;
+    let extensions = chosen.extensions.clone();
+    let timeline_semaphores = chosen.timeline_semaphores;
 
-    DeviceInfo {
-        logical_devices,
+    Ok(DeviceInfo {
+        logical_devices: candidates
+            .into_iter()
+            .map(|candidate| candidate.logical_device)
+            .collect(),
         device,
         queue_families,
         queue,
+        graphics_family,
+        present_queue,
+        present_family,
+        transfer_queue,
+        transfer_family,
+        compute_queue,
+        compute_family,
+        features,
+        extensions,
+        timeline_semaphores,
+    })
+}
+
+/// Checks one device against `requirements`, returning why it can't be used otherwise.
+fn evaluate(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
+    surface: Option<&SurfaceInfo>,
+    requirements: &DeviceRequirements,
+) -> std::result::Result<Candidate, String> {
+    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+    let presents = |index: usize| match surface {
+        Some(surface) => unsafe {
+            surface.surface_loader.get_physical_device_surface_support(
+                physical_device,
+                index as u32,
+                surface.surface,
+            )
+        }
+        .map_err(|e| format!("failed to query present support: {e}")),
+        None => Ok(true),
+    };
+
+    let mut graphics_family = None;
+    let mut present_family = None;
+    for (index, family) in families.iter().enumerate() {
+        let graphics = family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
+        let present = presents(index)?;
+
+        // A family that does both saves handing images from one queue to the other.
+        if graphics && present {
+            graphics_family = Some(index);
+            present_family = Some(index);
+            break;
+        }
+        if graphics && graphics_family.is_none() {
+            graphics_family = Some(index);
+        }
+        if present && present_family.is_none() {
+            present_family = Some(index);
+        }
+    }
+
+    let Some(graphics_family) = graphics_family else {
+        return Err("no graphics queue family".to_owned());
+    };
+    let Some(present_family) = present_family else {
+        return Err("no queue family can present to the window".to_owned());
+    };
+
+    let transfer_family = transfer_family(&families, graphics_family);
+
+    let compute_family = families
+        .iter()
+        .position(|family| {
+            family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|index| index as u32);
+
+    let available: Vec<String> =
+        unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .map_err(|e| format!("failed to query extensions: {e}"))?
+            .iter()
+            .map(|extension| {
+                unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+
+    let missing: Vec<&str> = requirements
+        .extensions
+        .iter()
+        .filter(|name| !available.contains(name))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("missing extensions {}", missing.join(", ")));
+    }
+
+    let supported = unsafe { instance.get_physical_device_features(physical_device) };
+    let missing = missing_features(&requirements.features, &supported);
+    if !missing.is_empty() {
+        return Err(format!("missing features {}", missing.join(", ")));
     }
+
+    let unmet: Vec<&str> = requirements
+        .limits
+        .iter()
+        .filter(|limit| !(limit.check)(&properties.limits))
+        .map(|limit| limit.name)
+        .collect();
+    if !unmet.is_empty() {
+        return Err(format!("limits not met: {}", unmet.join(", ")));
+    }
+
+    let mut extensions = requirements.extensions.clone();
+    extensions.extend(
+        requirements
+            .optional_extensions
+            .iter()
+            .filter(|name| available.contains(name) && !extensions.contains(name))
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+
+    // Wireframe materials need this; it's turned on whenever the device has it.
+    let engine_features = vk::PhysicalDeviceFeatures {
+        fill_mode_non_solid: vk::TRUE,
+        ..Default::default()
+    };
+    let features = supported_features(
+        &[
+            &requirements.features,
+            &requirements.optional_features,
+            &engine_features,
+        ],
+        &supported,
+    );
+
+    // Timeline semaphores are core in Vulkan 1.2, but still a feature to turn on.
+    let timeline_semaphores = properties.api_version >= vk::API_VERSION_1_2 && {
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut features2 =
+            vk::PhysicalDeviceFeatures2::builder().push_next(&mut timeline_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+        timeline_features.timeline_semaphore == vk::TRUE
+    };
+
+    let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let local_memory = memory.memory_heaps[..memory.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    Ok(Candidate {
+        logical_device: LogicalDevice {
+            physical_device,
+            priority: device_priority(properties.device_type, local_memory),
+            properties,
+        },
+        graphics_family: graphics_family as u32,
+        present_family: present_family as u32,
+        transfer_family: transfer_family as u32,
+        compute_family,
+        extensions,
+        features,
+        timeline_semaphores,
+    })
+}
+
+/// The family uploads should go to: a transfer-only one if there is one, else any family
+/// without graphics, so copies don't queue up behind rendering, else `graphics_family`.
+/// Graphics and compute families support transfers whether they say so or not.
+pub fn transfer_family(families: &[vk::QueueFamilyProperties], graphics_family: usize) -> usize {
+    families
+        .iter()
+        .position(|family| {
+            family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !family
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .or_else(|| {
+            families.iter().position(|family| {
+                family
+                    .queue_flags
+                    .intersects(vk::QueueFlags::TRANSFER | vk::QueueFlags::COMPUTE)
+                    && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+        })
+        .unwrap_or(graphics_family)
 }
 
+/// Whether `selector` picks the device `name` at `index` in enumeration order: either the
+/// index itself or part of the name, ignoring case. `lavapipe` stands for the name the driver
+/// reports, `llvmpipe`.
+pub fn matches_selector(selector: &str, index: usize, name: &str) -> bool {
+    let selector = selector.trim().to_lowercase();
+    match selector.parse::<usize>() {
+        Ok(selected) => selected == index,
+        Err(_) => {
+            let selector = match selector.as_str() {
+                "lavapipe" => "llvmpipe",
+                selector => selector,
+            };
+            name.to_lowercase().contains(selector)
+        }
+    }
+}
+
+/// Ranks devices by type first and by device-local memory second.
+pub fn device_priority(device_type: vk::PhysicalDeviceType, local_memory: u64) -> u64 {
+    let type_rank: u64 = match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        _ => 1,
+    };
+    let memory_mib = (local_memory >> 20).min(u64::from(u32::MAX));
+    (type_rank << 32) | memory_mib
+}
+
+fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Generates the helpers that go over every field of `vk::PhysicalDeviceFeatures`.
+macro_rules! features {
+    ($($name:ident),* $(,)?) => {
+        /// Names of the features turned on in `required` but not in `supported`.
+        pub fn missing_features(
+            required: &vk::PhysicalDeviceFeatures,
+            supported: &vk::PhysicalDeviceFeatures,
+        ) -> Vec<&'static str> {
+            let mut missing = Vec::new();
+            $(
+                if required.$name == vk::TRUE && supported.$name != vk::TRUE {
+                    missing.push(stringify!($name));
+                }
+            )*
+            missing
+        }
+
+        /// The features turned on in any of `wanted` that `supported` has.
+        fn supported_features(
+            wanted: &[&vk::PhysicalDeviceFeatures],
+            supported: &vk::PhysicalDeviceFeatures,
+        ) -> vk::PhysicalDeviceFeatures {
+            vk::PhysicalDeviceFeatures {
+                $(
+                    $name: (supported.$name == vk::TRUE
+                        && wanted.iter().any(|features| features.$name == vk::TRUE))
+                        .into(),
+                )*
+            }
+        }
+    };
+}
+
+features!(
+    robust_buffer_access,
+    full_draw_index_uint32,
+    image_cube_array,
+    independent_blend,
+    geometry_shader,
+    tessellation_shader,
+    sample_rate_shading,
+    dual_src_blend,
+    logic_op,
+    multi_draw_indirect,
+    draw_indirect_first_instance,
+    depth_clamp,
+    depth_bias_clamp,
+    fill_mode_non_solid,
+    depth_bounds,
+    wide_lines,
+    large_points,
+    alpha_to_one,
+    multi_viewport,
+    sampler_anisotropy,
+    texture_compression_etc2,
+    texture_compression_astc_ldr,
+    texture_compression_bc,
+    occlusion_query_precise,
+    pipeline_statistics_query,
+    vertex_pipeline_stores_and_atomics,
+    fragment_stores_and_atomics,
+    shader_tessellation_and_geometry_point_size,
+    shader_image_gather_extended,
+    shader_storage_image_extended_formats,
+    shader_storage_image_multisample,
+    shader_storage_image_read_without_format,
+    shader_storage_image_write_without_format,
+    shader_uniform_buffer_array_dynamic_indexing,
+    shader_sampled_image_array_dynamic_indexing,
+    shader_storage_buffer_array_dynamic_indexing,
+    shader_storage_image_array_dynamic_indexing,
+    shader_clip_distance,
+    shader_cull_distance,
+    shader_float64,
+    shader_int64,
+    shader_int16,
+    shader_resource_residency,
+    shader_resource_min_lod,
+    sparse_binding,
+    sparse_residency_buffer,
+    sparse_residency_image2_d,
+    sparse_residency_image3_d,
+    sparse_residency2_samples,
+    sparse_residency4_samples,
+    sparse_residency8_samples,
+    sparse_residency16_samples,
+    sparse_residency_aliased,
+    variable_multisample_rate,
+    inherited_queries,
+);
+
 impl std::fmt::Display for LogicalDevice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let device_name = std::str::from_utf8(unsafe {
@@ -113,4 +584,4 @@ impl std::fmt::Display for LogicalDevice {
             device_name.unwrap_or("Unknown device")
         )
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,81 @@
+use ash::vk;
+
+use cgmath::SquareMatrix;
+
+/// One draw call. When `index_buffer` is null the entry is drawn without indices and
+/// `index_count` is used as the vertex count. `transform` is the object's model matrix and
+/// reaches the vertex shader as a push constant.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawCommand {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub index_count: u32,
+    pub index_type: vk::IndexType,
+    pub pipeline: vk::Pipeline,
+    pub transform: cgmath::Matrix4<f32>,
+}
+
+impl DrawCommand {
+    /// Draws `index_count` `u32` indices from `index_buffer`.
+    pub fn indexed(
+        vertex_buffer: vk::Buffer,
+        index_buffer: vk::Buffer,
+        index_count: u32,
+        pipeline: vk::Pipeline,
+    ) -> Self {
+        DrawCommand {
+            vertex_buffer,
+            index_buffer,
+            index_count,
+            index_type: vk::IndexType::UINT32,
+            pipeline,
+            transform: cgmath::Matrix4::identity(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: cgmath::Matrix4<f32>) -> Self {
+        self.transform = transform;
+        self
+    }
+}
+
+/// The draw calls recorded into every frame, in submission order. Entries stay in the list
+/// until they are removed, so a static scene only has to be queued once.
+#[derive(Clone, Default)]
+pub struct DrawList {
+    commands: Vec<DrawCommand>,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&DrawCommand) -> bool) {
+        self.commands.retain(f);
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn commands_mut(&mut self) -> &mut [DrawCommand] {
+        &mut self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
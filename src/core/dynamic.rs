@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::ptr;
+
+use ash::vk;
+use gpu_allocator::vulkan;
+
+use super::buffers::{create_buffer, destroy_buffer, Buffer};
+use super::error::Result;
+
+/// Smallest size a dynamic buffer is created with, so small writes don't regrow it every frame.
+const MIN_CAPACITY: u64 = 256;
+
+/// Holds resources the GPU may still use until the submission that last used them is done,
+/// keyed by the timeline value that submission signals.
+pub struct DeletionQueue<T> {
+    pending: VecDeque<(u64, T)>,
+}
+
+impl<T> Default for DeletionQueue<T> {
+    fn default() -> Self {
+        DeletionQueue {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DeletionQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `item`, which is free once the timeline reaches `value`. Values have to be pushed
+    /// in increasing order, which holds for values read from one timeline.
+    pub fn push(&mut self, value: u64, item: T) {
+        self.pending.push_back((value, item));
+    }
+
+    /// Removes the items whose value is at most `completed`, the value the timeline has
+    /// reached.
+    pub fn collect(&mut self, completed: u64) -> Vec<T> {
+        let mut done = Vec::new();
+        while self
+            .pending
+            .front()
+            .is_some_and(|(value, _)| *value <= completed)
+        {
+            let (_, item) = self
+                .pending
+                .pop_front()
+                .expect("Failed to get pending item");
+            done.push(item);
+        }
+        done
+    }
+
+    /// Removes every item regardless of age, once the device is idle.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.pending.drain(..).map(|(_, item)| item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Refers to a buffer created by [`App::create_dynamic_buffer`](super::app::App).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DynamicBufferId(pub(crate) usize);
+
+/// A host-visible buffer for data that changes every frame, such as UI or debug lines. Each
+/// frame in flight writes its own copy, so rewriting one never races the GPU reading another.
+/// A copy that is too small is replaced by a bigger one.
+pub(crate) struct DynamicBuffer {
+    name: String,
+    usage: vk::BufferUsageFlags,
+    frames: Vec<Option<(Buffer, u64)>>,
+}
+
+impl DynamicBuffer {
+    pub(crate) fn new(name: &str, usage: vk::BufferUsageFlags, frames_in_flight: usize) -> Self {
+        DynamicBuffer {
+            name: name.to_owned(),
+            usage,
+            frames: (0..frames_in_flight).map(|_| None).collect(),
+        }
+    }
+
+    /// Copies `data` into the buffer of `frame`, which must not be in use by the GPU. Returns
+    /// that buffer, and the buffer it replaced if it had to grow.
+    pub(crate) fn write(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        frame: usize,
+        data: &[u8],
+    ) -> Result<(vk::Buffer, Option<Buffer>)> {
+        let size = data.len() as u64;
+        let mut replaced = None;
+
+        let slot = &mut self.frames[frame];
+        if slot.as_ref().is_none_or(|(_, capacity)| *capacity < size) {
+            let capacity = size.next_power_of_two().max(MIN_CAPACITY);
+            let (buffer, allocation) = create_buffer(
+                device,
+                allocator,
+                capacity,
+                format!("{} Dynamic Buffer", self.name).as_str(),
+                &[],
+                self.usage,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+
+            let buffer = Buffer {
+                name: self.name.clone(),
+                buffer,
+                buffer_type: self.usage,
+                allocation: Some(allocation),
+            };
+            replaced = slot.replace((buffer, capacity)).map(|(buffer, _)| buffer);
+        }
+
+        let (buffer, _) = slot.as_ref().expect("Failed to get dynamic buffer");
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                buffer
+                    .allocation
+                    .as_ref()
+                    .expect("Failed to get allocation")
+                    .mapped_ptr()
+                    .expect("Failed to get pointer")
+                    .as_ptr() as *mut u8,
+                data.len(),
+            )
+        };
+
+        Ok((buffer.buffer, replaced))
+    }
+
+    /// The buffer last written for `frame`, if any.
+    pub(crate) fn buffer(&self, frame: usize) -> Option<vk::Buffer> {
+        self.frames[frame].as_ref().map(|(buffer, _)| buffer.buffer)
+    }
+
+    pub(crate) fn destroy(self, device: &ash::Device, allocator: &mut vulkan::Allocator) {
+        for (buffer, _) in self.frames.into_iter().flatten() {
+            if let Err(e) = destroy_buffer(device, allocator, buffer) {
+                error!("Failed to free {} dynamic buffer: {e}", self.name);
+            }
+        }
+    }
+}
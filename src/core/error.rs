@@ -0,0 +1,142 @@
+use ash::vk;
+
+use super::shader_compiler::CompileError;
+
+#[derive(Debug)]
+pub enum VkcrError {
+    Vulkan(vk::Result),
+    Allocation(gpu_allocator::AllocationError),
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    Shader {
+        path: String,
+        message: String,
+    },
+    ShaderCompile(CompileError),
+    Texture {
+        path: String,
+        message: String,
+    },
+    Mesh {
+        path: String,
+        message: String,
+    },
+    Window(winit::error::OsError),
+    Image {
+        path: String,
+        message: String,
+    },
+    /// A rendered image doesn't match the golden image at `path`. It was written to `actual`,
+    /// and for images of the same size the mismatching pixels are marked in `diff`.
+    GoldenMismatch {
+        path: String,
+        reason: String,
+        actual: String,
+        diff: Option<String>,
+    },
+    /// An upload was given no data, which Vulkan can't copy or create a buffer for.
+    EmptyUpload {
+        name: String,
+    },
+    /// Every device was rejected, for the reason given next to its name.
+    NoSuitableDevice {
+        rejected: Vec<String>,
+    },
+    /// The device lacks a feature or limit the configuration asks for.
+    Unsupported(String),
+}
+
+pub type Result<T> = std::result::Result<T, VkcrError>;
+
+impl std::fmt::Display for VkcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VkcrError::Vulkan(result) => write!(f, "Vulkan call failed: {result}"),
+            VkcrError::Allocation(e) => write!(f, "GPU allocation failed: {e}"),
+            VkcrError::Io { path, source } => write!(f, "Failed to access {path}: {source}"),
+            VkcrError::Shader { path, message } => {
+                write!(f, "Failed to load shader {path}: {message}")
+            }
+            VkcrError::ShaderCompile(e) => write!(f, "Failed to compile shader {e}"),
+            VkcrError::Texture { path, message } => {
+                write!(f, "Failed to load texture {path}: {message}")
+            }
+            VkcrError::Mesh { path, message } => {
+                write!(f, "Failed to load mesh {path}: {message}")
+            }
+            VkcrError::Window(e) => write!(f, "Failed to create window: {e}"),
+            VkcrError::Image { path, message } => {
+                write!(f, "Failed to access image {path}: {message}")
+            }
+            VkcrError::GoldenMismatch {
+                path,
+                reason,
+                actual,
+                diff,
+            } => {
+                write!(
+                    f,
+                    "{path} doesn't match: {reason}; rendered image written to {actual}"
+                )?;
+                match diff {
+                    Some(diff) => write!(f, ", diff written to {diff}"),
+                    None => Ok(()),
+                }
+            }
+            VkcrError::EmptyUpload { name } => write!(f, "Failed to upload {name}: no data"),
+            VkcrError::NoSuitableDevice { rejected } if rejected.is_empty() => {
+                write!(f, "No devices that support Vulkan were found")
+            }
+            VkcrError::NoSuitableDevice { rejected } => {
+                write!(f, "No suitable device found: {}", rejected.join("; "))
+            }
+            VkcrError::Unsupported(what) => write!(f, "{what} is not supported by the device"),
+        }
+    }
+}
+
+impl std::error::Error for VkcrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VkcrError::Vulkan(result) => Some(result),
+            VkcrError::Allocation(e) => Some(e),
+            VkcrError::Io { source, .. } => Some(source),
+            VkcrError::ShaderCompile(e) => Some(e),
+            VkcrError::Window(e) => Some(e),
+            VkcrError::Shader { .. }
+            | VkcrError::Texture { .. }
+            | VkcrError::Mesh { .. }
+            | VkcrError::Image { .. }
+            | VkcrError::GoldenMismatch { .. }
+            | VkcrError::EmptyUpload { .. }
+            | VkcrError::NoSuitableDevice { .. }
+            | VkcrError::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<vk::Result> for VkcrError {
+    fn from(result: vk::Result) -> Self {
+        VkcrError::Vulkan(result)
+    }
+}
+
+impl From<gpu_allocator::AllocationError> for VkcrError {
+    fn from(e: gpu_allocator::AllocationError) -> Self {
+        VkcrError::Allocation(e)
+    }
+}
+
+impl From<CompileError> for VkcrError {
+    fn from(e: CompileError) -> Self {
+        VkcrError::ShaderCompile(e)
+    }
+}
+
+impl From<winit::error::OsError> for VkcrError {
+    fn from(e: winit::error::OsError) -> Self {
+        VkcrError::Window(e)
+    }
+}
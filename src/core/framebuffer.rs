@@ -1,27 +1,41 @@
 use ash::vk;
 
-use super::{swapchain::SwapchainInfo, pipeline::PipelineInfo};
+use super::error::Result;
 
+/// One framebuffer per view in `views`. With a multisampled `color_view` the views are the
+/// resolve targets and `color_view` is drawn to.
 pub fn create_framebuffers(
-    swapchain_info: SwapchainInfo,
-    pipeline_info: PipelineInfo,
+    views: &[vk::ImageView],
+    depth_view: vk::ImageView,
+    color_view: Option<vk::ImageView>,
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
     device: &ash::Device,
-) -> Vec<vk::Framebuffer> {
-    let mut framebuffers = Vec::<vk::Framebuffer>::new();
-    framebuffers.reserve(swapchain_info.swapchain_views.len());
-    for view in &swapchain_info.swapchain_views {
-        let attachments = [*view];
+) -> Result<Vec<vk::Framebuffer>> {
+    let mut framebuffers = Vec::<vk::Framebuffer>::with_capacity(views.len());
+    for view in views {
+        let attachments = match color_view {
+            Some(color_view) => vec![color_view, depth_view, *view],
+            None => vec![*view, depth_view],
+        };
         let framebuffer_info = vk::FramebufferCreateInfo::builder()
-            .render_pass(pipeline_info.render_pass)
+            .render_pass(render_pass)
             .attachments(&attachments)
-            .width(swapchain_info.extent.width)
-            .height(swapchain_info.extent.height)
+            .width(extent.width)
+            .height(extent.height)
             .layers(1);
 
-        let framebuffer = unsafe { device.create_framebuffer(&framebuffer_info, None) }
-            .expect("Failed to create framebuffer");
+        let framebuffer = match unsafe { device.create_framebuffer(&framebuffer_info, None) } {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                for framebuffer in framebuffers {
+                    unsafe { device.destroy_framebuffer(framebuffer, None) };
+                }
+                return Err(e.into());
+            }
+        };
 
         framebuffers.push(framebuffer);
     }
-    framebuffers
+    Ok(framebuffers)
 }
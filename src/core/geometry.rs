@@ -7,7 +7,12 @@ pub static QUAD_VERTICES: [Vertex; 4] = [
             y: 0.0,
             z: 0.0,
         },
-        pos: cgmath::Vector2 { x: -0.5, y: -0.5 },
+        pos: cgmath::Vector3 {
+            x: -0.5,
+            y: -0.5,
+            z: 0.0,
+        },
+        uv: cgmath::Vector2 { x: 0.0, y: 0.0 },
     },
     Vertex {
         color: cgmath::Vector3 {
@@ -15,7 +20,12 @@ pub static QUAD_VERTICES: [Vertex; 4] = [
             y: 0.0,
             z: 1.0,
         },
-        pos: cgmath::Vector2 { x: 0.5, y: -0.5 },
+        pos: cgmath::Vector3 {
+            x: 0.5,
+            y: -0.5,
+            z: 0.0,
+        },
+        uv: cgmath::Vector2 { x: 1.0, y: 0.0 },
     },
     Vertex {
         color: cgmath::Vector3 {
@@ -23,7 +33,12 @@ pub static QUAD_VERTICES: [Vertex; 4] = [
             y: 1.0,
             z: 1.0,
         },
-        pos: cgmath::Vector2 { x: 0.5, y: 0.5 },
+        pos: cgmath::Vector3 {
+            x: 0.5,
+            y: 0.5,
+            z: 0.0,
+        },
+        uv: cgmath::Vector2 { x: 1.0, y: 1.0 },
     },
     Vertex {
         color: cgmath::Vector3 {
@@ -31,8 +46,13 @@ pub static QUAD_VERTICES: [Vertex; 4] = [
             y: 1.0,
             z: 0.0,
         },
-        pos: cgmath::Vector2 { x: -0.5, y: 0.5 },
+        pos: cgmath::Vector3 {
+            x: -0.5,
+            y: 0.5,
+            z: 0.0,
+        },
+        uv: cgmath::Vector2 { x: 0.0, y: 1.0 },
     },
 ];
 
-pub static QUAD_INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+pub static QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use super::camera::Camera;
+use super::error::{self, VkcrError};
+use super::headless::Headless;
+use super::vertex::Vertex;
+
+/// When set to `1`, [`check_golden`] writes the rendered image as the new golden image instead
+/// of comparing against it.
+pub const UPDATE_GOLDEN: &str = "VKCR_UPDATE_GOLDEN";
+
+/// When set to `1`, golden tests pass without rendering on machines without a Vulkan device.
+/// Otherwise a missing device fails them, so CI can't go green without running them.
+pub const SKIP_GPU_TESTS: &str = "VKCR_SKIP_GPU_TESTS";
+
+pub struct Scene<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub vertices: &'a [Vertex],
+    pub indices: &'a [u32],
+    pub transform: cgmath::Matrix4<f32>,
+    pub camera: Camera,
+}
+
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub max_difference: u8,
+    pub diff: image::RgbaImage,
+}
+
+pub fn render_scene(scene: Scene) -> error::Result<image::RgbaImage> {
+    let mut headless =
+        Headless::with_geometry(scene.width, scene.height, scene.vertices, scene.indices)?;
+    for draw in headless.draw_list_mut().commands_mut() {
+        draw.transform = scene.transform;
+    }
+    headless.render(scene.camera)
+}
+
+/// Compares two images of the same size channel by channel. A pixel mismatches when any of its
+/// channels differs by more than `tolerance`; those pixels are painted red in the diff image,
+/// every other pixel is a dimmed grayscale copy of `expected`.
+pub fn compare(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+    tolerance: u8,
+) -> Comparison {
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "Images must have the same dimensions"
+    );
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = image::RgbaImage::new(expected.width(), expected.height());
+
+    for ((actual, expected), diff) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let difference = actual
+            .0
+            .iter()
+            .zip(expected.0.iter())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+
+        max_difference = max_difference.max(difference);
+
+        *diff = if difference > tolerance {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0;
+            let luma = ((u16::from(r) + u16::from(g) + u16::from(b)) / 12) as u8;
+            image::Rgba([luma, luma, luma, 255])
+        };
+    }
+
+    Comparison {
+        mismatched_pixels,
+        max_difference,
+        diff,
+    }
+}
+
+/// Checks `actual` against the PNG at `golden_path`. On failure the rendered image and a diff
+/// image are written next to the golden image as `<name>.actual.png` and `<name>.diff.png`.
+pub fn check_golden(
+    actual: &image::RgbaImage,
+    golden_path: &Path,
+    tolerance: u8,
+) -> error::Result<()> {
+    if std::env::var(UPDATE_GOLDEN).unwrap_or_else(|_| "0".to_string()) == "1" {
+        if let Some(parent) = golden_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|source| VkcrError::Io {
+                path: parent.display().to_string(),
+                source,
+            })?;
+        }
+        return save(actual, golden_path);
+    }
+
+    let expected = image::open(golden_path)
+        .map_err(|e| VkcrError::Image {
+            path: golden_path.display().to_string(),
+            message: format!("{e}. Run with {UPDATE_GOLDEN}=1 to create it"),
+        })?
+        .to_rgba8();
+
+    let actual_path = sibling_path(golden_path, "actual");
+
+    if actual.dimensions() != expected.dimensions() {
+        save(actual, &actual_path)?;
+        return Err(VkcrError::GoldenMismatch {
+            path: golden_path.display().to_string(),
+            reason: format!(
+                "rendered image is {:?} but the golden image is {:?}",
+                actual.dimensions(),
+                expected.dimensions()
+            ),
+            actual: actual_path.display().to_string(),
+            diff: None,
+        });
+    }
+
+    let comparison = compare(actual, &expected, tolerance);
+
+    if comparison.mismatched_pixels == 0 {
+        return Ok(());
+    }
+
+    let diff_path = sibling_path(golden_path, "diff");
+
+    save(actual, &actual_path)?;
+    save(&comparison.diff, &diff_path)?;
+
+    Err(VkcrError::GoldenMismatch {
+        path: golden_path.display().to_string(),
+        reason: format!(
+            "{} pixels differ by more than {tolerance} (max difference {})",
+            comparison.mismatched_pixels, comparison.max_difference
+        ),
+        actual: actual_path.display().to_string(),
+        diff: Some(diff_path.display().to_string()),
+    })
+}
+
+fn sibling_path(golden_path: &Path, suffix: &str) -> PathBuf {
+    let stem = golden_path
+        .file_stem()
+        .and_then(std::ffi::OsStr::to_str)
+        .unwrap_or("golden");
+    golden_path.with_file_name(format!("{stem}.{suffix}.png"))
+}
+
+fn save(image: &image::RgbaImage, path: &Path) -> error::Result<()> {
+    image.save(path).map_err(|e| VkcrError::Image {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
@@ -0,0 +1,553 @@
+use ash::vk;
+
+use gpu_allocator::vulkan;
+
+use super::buffers::{
+    create_allocator, create_buffer, create_descriptor_sets, create_index_buffer,
+    create_uniform_buffers, create_vertex_buffer, destroy_buffer, Buffer,
+};
+use super::camera::Camera;
+use super::commands::{create_command_pool, record_buffer, CommandInfo, TransferQueue};
+use super::debug::{create_debug, DebugInfo};
+use super::device::{create_device, DeviceInfo, DeviceRequirements};
+use super::draw::{DrawCommand, DrawList};
+use super::error::Result;
+use super::framebuffer::create_framebuffers;
+use super::geometry::{QUAD_INDICES, QUAD_VERTICES};
+use super::images::{create_depth_image, create_image, destroy_image, Image};
+use super::instance::{create_instance, InstanceInfo};
+use super::pipeline::{destroy_pipeline, PipelineBuilder, PipelineInfo};
+use super::reflect::ShaderLayout;
+use super::swapchain::ColorOutput;
+use super::textures::{create_default_texture, destroy_texture, Texture};
+use super::timeline::Timeline;
+use super::upload::{UploadManager, STAGING_SIZE};
+use super::vertex::Vertex;
+
+/// A UNORM target, so rendered images hold the linear values the shaders write for
+/// [`ColorOutput::Srgb`] instead of the sRGB encoding an sRGB swapchain applies on screen.
+/// Golden images are stored the same way and look darker than the window does.
+const TARGET_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// Renders into an offscreen color image instead of a swapchain, so frames can be
+/// produced and read back without a window, a surface or a display.
+pub struct Headless {
+    instance_info: InstanceInfo,
+    debug_info: DebugInfo,
+    device_info: DeviceInfo,
+    allocator: Option<vulkan::Allocator>,
+    command_info: CommandInfo,
+    pipeline_info: Option<PipelineInfo>,
+    target: Option<Image>,
+    depth_image: Option<Image>,
+    texture: Option<Texture>,
+    framebuffer: vk::Framebuffer,
+    readback: Option<Buffer>,
+    buffers: Vec<Buffer>,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    fence: vk::Fence,
+    draw_list: DrawList,
+}
+
+impl Headless {
+    pub fn init(width: u32, height: u32) -> Result<Headless> {
+        Headless::with_geometry(width, height, &QUAD_VERTICES, &QUAD_INDICES)
+    }
+
+    /// Same as [`Headless::init`], but draws the given vertices and indices instead of the
+    /// default quad.
+    pub fn with_geometry(
+        width: u32,
+        height: u32,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<Headless> {
+        let instance_info = create_instance(&[])?;
+        let instance = &instance_info.instance;
+
+        let debug_info = match create_debug(&instance_info.entry, instance) {
+            Ok(debug_info) => debug_info,
+            Err(e) => {
+                destroy_context(&instance_info, None, None);
+                return Err(e);
+            }
+        };
+
+        let device_info = match create_device(instance, None, &DeviceRequirements::default()) {
+            Ok(device_info) => device_info,
+            Err(e) => {
+                destroy_context(&instance_info, Some(&debug_info), None);
+                return Err(e);
+            }
+        };
+
+        let allocator = match create_allocator(instance, &device_info) {
+            Ok(allocator) => allocator,
+            Err(e) => {
+                destroy_context(&instance_info, Some(&debug_info), Some(&device_info.device));
+                return Err(e);
+            }
+        };
+
+        // Dropping it frees whatever was created so far, so the rest can fail at any step.
+        let mut headless = Headless {
+            instance_info,
+            debug_info,
+            device_info,
+            allocator: Some(allocator),
+            command_info: CommandInfo {
+                command_pool: vk::CommandPool::null(),
+                command_buffers: Vec::new(),
+            },
+            pipeline_info: None,
+            target: None,
+            depth_image: None,
+            texture: None,
+            framebuffer: vk::Framebuffer::null(),
+            readback: None,
+            buffers: Vec::new(),
+            descriptor_sets: Vec::new(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set_layouts: Vec::new(),
+            fence: vk::Fence::null(),
+            draw_list: DrawList::new(),
+        };
+
+        headless.create_resources(vk::Extent2D { width, height }, vertices, indices)?;
+
+        Ok(headless)
+    }
+
+    fn create_resources(
+        &mut self,
+        extent: vk::Extent2D,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<()> {
+        let index_count = indices.len().try_into().expect("Failed to convert to u32");
+
+        let device_info = &self.device_info;
+        let allocator = self.allocator.as_mut().expect("Failed to get allocator");
+
+        self.command_info = create_command_pool(
+            device_info
+                .queue_families
+                .first()
+                .expect("Failed to get queue family"),
+            &device_info.device,
+            2,
+        )?;
+
+        // Everything is uploaded once up front, so the graphics queue does the copies too.
+        let transfer = TransferQueue {
+            command_pool: self.command_info.command_pool,
+            queue: device_info.queue,
+            queue_families: Vec::new(),
+        };
+        let mut uploads = UploadManager::new(
+            &device_info.device,
+            allocator,
+            transfer,
+            Timeline::new(&device_info.device, device_info.timeline_semaphores)?,
+            STAGING_SIZE,
+        )?;
+
+        let uploaded = upload_resources(
+            &device_info.device,
+            allocator,
+            &mut uploads,
+            vertices,
+            indices,
+            &mut self.texture,
+            &mut self.buffers,
+        );
+        let finished = uploads.destroy(&device_info.device, allocator);
+        uploaded.and(finished)?;
+
+        let first_uniform = self.buffers.len();
+        self.buffers.extend(create_uniform_buffers(
+            Camera::default(),
+            allocator,
+            &device_info.device,
+            1,
+        )?);
+
+        let shader_layout = ShaderLayout::load("assets/shaders/default")?;
+
+        (
+            self.descriptor_sets,
+            self.descriptor_pool,
+            self.descriptor_set_layouts,
+        ) = create_descriptor_sets(
+            &device_info.device,
+            &shader_layout,
+            &self.buffers[first_uniform..],
+            Camera::default(),
+            self.texture.as_ref().expect("Failed to get texture"),
+        )?;
+
+        // One-off renders for tests gain nothing from a persistent cache.
+        let pipeline_info = self.pipeline_info.insert(PipelineBuilder::default().build(
+            device_info,
+            vk::PipelineCache::null(),
+            &extent,
+            TARGET_FORMAT,
+            ColorOutput::Srgb,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            &self.descriptor_set_layouts,
+        )?);
+
+        let target = self.target.insert(create_image(
+            &device_info.device,
+            allocator,
+            extent,
+            TARGET_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+            &[],
+            "Headless Target",
+        )?);
+
+        let depth_image = self.depth_image.insert(create_depth_image(
+            &device_info.device,
+            allocator,
+            extent,
+            pipeline_info.samples,
+        )?);
+
+        self.framebuffer = *create_framebuffers(
+            &[target.view],
+            depth_image.view,
+            None,
+            extent,
+            pipeline_info.render_pass,
+            &device_info.device,
+        )?
+        .first()
+        .expect("Failed to create framebuffer");
+
+        let (readback_buffer, readback_allocation) = create_buffer(
+            &device_info.device,
+            allocator,
+            u64::from(extent.width) * u64::from(extent.height) * 4,
+            "Readback Buffer",
+            &[],
+            vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuToCpu,
+        )?;
+
+        self.readback = Some(Buffer {
+            name: "Readback".to_owned(),
+            buffer: readback_buffer,
+            buffer_type: vk::BufferUsageFlags::TRANSFER_DST,
+            allocation: Some(readback_allocation),
+        });
+
+        self.draw_list.push(DrawCommand::indexed(
+            self.buffers[0].buffer,
+            self.buffers[1].buffer,
+            index_count,
+            *pipeline_info
+                .pipeline
+                .first()
+                .expect("Failed to get pipeline"),
+        ));
+
+        self.fence = unsafe {
+            device_info
+                .device
+                .create_fence(&vk::FenceCreateInfo::builder(), None)
+        }?;
+
+        Ok(())
+    }
+
+    /// Draws one frame with the given camera and returns the rendered pixels.
+    pub fn render(&mut self, camera: Camera) -> Result<image::RgbaImage> {
+        let device = &self.device_info.device;
+        let target = self.target.as_ref().expect("Failed to get target image");
+        let readback = self
+            .readback
+            .as_ref()
+            .expect("Failed to get readback buffer");
+
+        let uniform_buffer = self
+            .buffers
+            .iter()
+            .find(|buffer| buffer.buffer_type == vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .expect("Failed to get uniform buffer");
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytemuck::cast_slice(&[camera]).as_ptr(),
+                uniform_buffer
+                    .allocation
+                    .as_ref()
+                    .expect("Failed to get allocation")
+                    .mapped_ptr()
+                    .expect("Memory is not host visible")
+                    .as_ptr() as *mut u8,
+                std::mem::size_of_val(&camera),
+            )
+        };
+
+        let render_command_buffer = self.command_info.command_buffers[0];
+        let copy_command_buffer = self.command_info.command_buffers[1];
+
+        record_buffer(
+            self.pipeline_info
+                .as_ref()
+                .expect("Failed to get pipeline info"),
+            self.framebuffer,
+            target.extent,
+            device,
+            render_command_buffer,
+            self.draw_list.commands(),
+            &[],
+            &[],
+            self.descriptor_sets.first().copied(),
+        )?;
+
+        unsafe {
+            device.begin_command_buffer(copy_command_buffer, &vk::CommandBufferBeginInfo::builder())
+        }?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(target.image)
+            .subresource_range(*subresource_range);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                copy_command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[*image_barrier],
+            )
+        };
+
+        let copy_region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: target.extent.width,
+                height: target.extent.height,
+                depth: 1,
+            });
+
+        unsafe {
+            device.cmd_copy_image_to_buffer(
+                copy_command_buffer,
+                target.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[*copy_region],
+            )
+        };
+
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(readback.buffer)
+            .size(vk::WHOLE_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                copy_command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[*buffer_barrier],
+                &[],
+            )
+        };
+
+        unsafe { device.end_command_buffer(copy_command_buffer) }?;
+
+        let command_buffers = [render_command_buffer, copy_command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+
+        unsafe { device.queue_submit(self.device_info.queue, &[*submit_info], self.fence) }?;
+
+        unsafe { device.wait_for_fences(&[self.fence], true, u64::MAX) }?;
+
+        unsafe { device.reset_fences(&[self.fence]) }?;
+
+        for command_buffer in command_buffers {
+            unsafe {
+                device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+            }?;
+        }
+
+        let pixels = readback
+            .allocation
+            .as_ref()
+            .expect("Failed to get allocation")
+            .mapped_slice()
+            .expect("Memory is not host visible");
+
+        Ok(
+            image::RgbaImage::from_raw(target.extent.width, target.extent.height, pixels.to_vec())
+                .expect("Failed to create image from readback buffer"),
+        )
+    }
+
+    /// Holds the geometry passed at creation; set per-object transforms through it.
+    pub fn draw_list_mut(&mut self) -> &mut DrawList {
+        &mut self.draw_list
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.target
+            .as_ref()
+            .expect("Failed to get target image")
+            .format
+    }
+
+    /// Frees everything, including what is missing or null after a failed
+    /// [`Headless::with_geometry`].
+    fn cleanup(&mut self) {
+        let device = &self.device_info.device;
+
+        if let Err(e) = unsafe { device.device_wait_idle() } {
+            error!("Failed to wait for device idle: {e}");
+        }
+
+        let mut allocator = self.allocator.take().expect("Failed to get allocator");
+
+        for buffer in self.buffers.drain(..).chain(self.readback.take()) {
+            debug!("Freeing {} buffer", buffer.name);
+            let name = buffer.name.clone();
+            if let Err(e) = destroy_buffer(device, &mut allocator, buffer) {
+                error!("Failed to free {name} allocation: {e}");
+            }
+        }
+
+        unsafe { device.destroy_framebuffer(self.framebuffer, None) };
+
+        for image in [self.target.take(), self.depth_image.take()]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(e) = destroy_image(device, &mut allocator, image) {
+                error!("Failed to free image: {e}");
+            }
+        }
+
+        if let Some(texture) = self.texture.take() {
+            if let Err(e) = destroy_texture(device, &mut allocator, texture) {
+                error!("Failed to free texture: {e}");
+            }
+        }
+
+        drop(allocator);
+
+        unsafe { device.destroy_fence(self.fence, None) };
+
+        unsafe { device.destroy_command_pool(self.command_info.command_pool, None) };
+
+        for set_layout in &self.descriptor_set_layouts {
+            unsafe { device.destroy_descriptor_set_layout(*set_layout, None) };
+        }
+
+        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+
+        if let Some(pipeline_info) = &self.pipeline_info {
+            destroy_pipeline(device, pipeline_info);
+        }
+
+        destroy_context(&self.instance_info, Some(&self.debug_info), Some(device));
+    }
+}
+
+/// Uploads the default texture and the geometry, whose vertex and index buffers end up first
+/// in `buffers`.
+fn upload_resources(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    uploads: &mut UploadManager,
+    vertices: &[Vertex],
+    indices: &[u32],
+    texture: &mut Option<Texture>,
+    buffers: &mut Vec<Buffer>,
+) -> Result<()> {
+    *texture = Some(create_default_texture(allocator, device, uploads)?);
+    buffers.push(create_vertex_buffer(vertices, allocator, device, uploads)?);
+    buffers.push(create_index_buffer(indices, allocator, device, uploads)?);
+    Ok(())
+}
+
+/// Destroys the device, the debug messenger and the instance, which everything else has to
+/// be gone from.
+fn destroy_context(
+    instance_info: &InstanceInfo,
+    debug_info: Option<&DebugInfo>,
+    device: Option<&ash::Device>,
+) {
+    if let Some(device) = device {
+        unsafe { device.destroy_device(None) };
+    }
+
+    if let Some(debug_info) = debug_info {
+        unsafe {
+            debug_info
+                .loader
+                .destroy_debug_utils_messenger(debug_info.messenger, None)
+        };
+    }
+
+    unsafe { instance_info.instance.destroy_instance(None) };
+}
+
+/// Returns whether a Vulkan instance can be created and exposes at least one device with a
+/// graphics queue. Test suites use this to tell a missing driver from a failed render.
+pub fn is_supported() -> bool {
+    let entry = ash::Entry::linked();
+
+    let instance = match unsafe { entry.create_instance(&vk::InstanceCreateInfo::builder(), None) }
+    {
+        Ok(instance) => instance,
+        Err(_) => return false,
+    };
+
+    let supported = unsafe { instance.enumerate_physical_devices() }
+        .unwrap_or_default()
+        .into_iter()
+        .any(|physical_device| {
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+                .iter()
+                .any(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+        });
+
+    unsafe { instance.destroy_instance(None) };
+
+    supported
+}
+
+impl Drop for Headless {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
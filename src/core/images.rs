@@ -0,0 +1,198 @@
+use ash::vk;
+
+use gpu_allocator::vulkan;
+
+use super::error::Result;
+
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+pub struct Image {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub allocation: Option<vulkan::Allocation>,
+}
+
+/// With more than one entry in `queue_families` the image is shared between those families.
+#[allow(clippy::too_many_arguments)]
+pub fn create_image(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+    queue_families: &[u32],
+    name: &str,
+) -> Result<Image> {
+    create_multisampled_image(
+        device,
+        allocator,
+        extent,
+        format,
+        usage,
+        aspect_mask,
+        vk::SampleCountFlags::TYPE_1,
+        queue_families,
+        name,
+    )
+}
+
+/// An image compute shaders can write and fragment shaders can sample. With more than one
+/// entry in `queue_families` it is shared between those families.
+pub fn create_storage_image(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    queue_families: &[u32],
+) -> Result<Image> {
+    create_multisampled_image(
+        device,
+        allocator,
+        extent,
+        format,
+        vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST,
+        vk::ImageAspectFlags::COLOR,
+        vk::SampleCountFlags::TYPE_1,
+        queue_families,
+        "Storage",
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_multisampled_image(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+    samples: vk::SampleCountFlags,
+    queue_families: &[u32],
+    name: &str,
+) -> Result<Image> {
+    let sharing_mode = if queue_families.len() > 1 {
+        vk::SharingMode::CONCURRENT
+    } else {
+        vk::SharingMode::EXCLUSIVE
+    };
+
+    let image_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(samples)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(sharing_mode)
+        .queue_family_indices(queue_families)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let image = unsafe { device.create_image(&image_info, None) }?;
+
+    let memory_requirements = unsafe { device.get_image_memory_requirements(image) };
+
+    let allocation = allocator.allocate(&vulkan::AllocationCreateDesc {
+        name: &format!("{name} allocation"),
+        requirements: memory_requirements,
+        location: gpu_allocator::MemoryLocation::GpuOnly,
+        linear: false,
+    })?;
+
+    unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset()) }?;
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(aspect_mask)
+        .level_count(1)
+        .base_mip_level(0)
+        .layer_count(1)
+        .base_array_layer(0);
+
+    let view_create_info = vk::ImageViewCreateInfo::builder()
+        .format(format)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .subresource_range(*subresource_range)
+        .image(image);
+
+    let view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+    Ok(Image {
+        image,
+        view,
+        format,
+        extent,
+        allocation: Some(allocation),
+    })
+}
+
+pub fn create_depth_image(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+) -> Result<Image> {
+    create_multisampled_image(
+        device,
+        allocator,
+        extent,
+        DEPTH_FORMAT,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageAspectFlags::DEPTH,
+        samples,
+        &[],
+        "Depth",
+    )
+}
+
+/// The multisampled color attachment that gets resolved into the final image. Only needed
+/// when rendering with more than one sample.
+pub fn create_color_target(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> Result<Option<Image>> {
+    if samples == vk::SampleCountFlags::TYPE_1 {
+        return Ok(None);
+    }
+
+    create_multisampled_image(
+        device,
+        allocator,
+        extent,
+        format,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::ImageAspectFlags::COLOR,
+        samples,
+        &[],
+        "Multisampled Color",
+    )
+    .map(Some)
+}
+
+pub fn destroy_image(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    mut image: Image,
+) -> Result<()> {
+    unsafe { device.destroy_image_view(image.view, None) };
+    unsafe { device.destroy_image(image.image, None) };
+
+    if let Some(allocation) = image.allocation.take() {
+        allocator.free(allocation)?;
+    }
+
+    Ok(())
+}
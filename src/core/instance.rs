@@ -0,0 +1,87 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use super::error::Result;
+
+const APP_NAME: &str = "VKCR\0";
+const ENGINE_NAME: &str = "VKCR Renderer\0";
+
+const API_DUMP: &str = "VK_LAYER_LUNARG_api_dump\0";
+const RENDERDOC_CAPTURE: &str = "VK_LAYER_RENDERDOC_Capture\0";
+
+const VALIDATION: &str = "VK_LAYER_KHRONOS_validation\0";
+
+#[derive(Clone)]
+pub struct InstanceInfo {
+    pub entry: ash::Entry,
+    pub instance: ash::Instance,
+}
+
+pub fn create_instance(extensions: &[*const i8]) -> Result<InstanceInfo> {
+    let mut instance_extensions: Vec<*const i8> =
+        vec![ash::extensions::ext::DebugUtils::name().as_ptr()];
+
+    instance_extensions.extend_from_slice(extensions);
+
+    let entry = ash::Entry::linked();
+
+    // Surfaces only report HDR and wide-gamut color spaces with this extension.
+    let colorspace = vk::ExtSwapchainColorspaceFn::name();
+    let available = entry.enumerate_instance_extension_properties(None)?;
+    if available
+        .iter()
+        .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == colorspace)
+    {
+        instance_extensions.push(colorspace.as_ptr());
+    }
+
+    let enable_api_dump = std::env::var("ENABLE_API_DUMP").unwrap_or_else(|_| "0".to_string());
+    let enable_renderdoc_capture =
+        std::env::var("ENABLE_RENDERDOC_CAPTURE").unwrap_or_else(|_| "0".to_string());
+    let enable_validation = std::env::var("ENABLE_VALIDATION").unwrap_or_else(|_| "0".to_string());
+
+    let application_info = vk::ApplicationInfo::builder()
+        .application_name(unsafe { CStr::from_ptr(APP_NAME.as_ptr() as *const i8) })
+        .application_version(vk::make_api_version(0, 0, 1, 0))
+        .engine_name(unsafe { CStr::from_ptr(ENGINE_NAME.as_ptr() as *const i8) })
+        .engine_version(vk::make_api_version(0, 0, 1, 0))
+        .api_version(vk::make_api_version(0, 1, 3, 239));
+
+    let layers = entry.enumerate_instance_layer_properties()?;
+
+    debug!("Available layers: ");
+
+    for layer in layers.iter() {
+        debug!(
+            "   {}",
+            std::str::from_utf8(unsafe {
+                &*(layer.layer_name.as_slice() as *const [i8] as *const [u8])
+            })
+            .expect("Failed to create string from layer name")
+        );
+    }
+
+    let mut instance_layers: Vec<*const i8> = Vec::new();
+
+    if enable_api_dump == "1" {
+        instance_layers.push(API_DUMP.as_ptr() as *const i8);
+    }
+
+    if enable_renderdoc_capture == "1" {
+        instance_layers.push(RENDERDOC_CAPTURE.as_ptr() as *const i8);
+    }
+
+    if enable_validation == "1" {
+        instance_layers.push(VALIDATION.as_ptr() as *const i8);
+    }
+
+    let instance_create_info = vk::InstanceCreateInfo::builder()
+        .application_info(&application_info)
+        .enabled_extension_names(instance_extensions.as_slice())
+        .enabled_layer_names(instance_layers.as_slice());
+
+    let instance: ash::Instance = unsafe { entry.create_instance(&instance_create_info, None) }?;
+
+    Ok(InstanceInfo { entry, instance })
+}
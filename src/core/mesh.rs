@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use super::error::{Result, VkcrError};
+use super::geometry::{QUAD_INDICES, QUAD_VERTICES};
+use super::vertex::Vertex;
+
+const WHITE: cgmath::Vector3<f32> = cgmath::Vector3 {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+};
+
+/// Vertex and index data ready to be uploaded with `create_vertex_buffer` and
+/// `create_index_buffer`.
+#[derive(Clone)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Default for Mesh {
+    fn default() -> Self {
+        Mesh {
+            vertices: QUAD_VERTICES.to_vec(),
+            indices: QUAD_INDICES.to_vec(),
+        }
+    }
+}
+
+impl Mesh {
+    /// Loads a `.gltf`, `.glb` or `.obj` file. Every primitive or model in the file is merged
+    /// into one mesh; vertices without a color are white and vertices without UVs use `(0, 0)`.
+    pub fn load(path: &str) -> Result<Mesh> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(std::ffi::OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("gltf") | Some("glb") => load_gltf(path),
+            Some("obj") => load_obj(path),
+            _ => Err(mesh_error(path, "unsupported file extension")),
+        }
+    }
+
+    fn append(&mut self, vertices: Vec<Vertex>, indices: impl Iterator<Item = u32>) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend(vertices);
+        self.indices.extend(indices.map(|index| index + offset));
+    }
+}
+
+fn load_gltf(path: &str) -> Result<Mesh> {
+    let (document, buffers, _) =
+        gltf::import(path).map_err(|e| mesh_error(path, &e.to_string()))?;
+
+    let mut mesh = Mesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for primitive in document.meshes().flat_map(|m| m.primitives()) {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            warn!("Skipping non-triangle primitive in {path}");
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let positions = reader
+            .read_positions()
+            .ok_or_else(|| mesh_error(path, "primitive has no positions"))?;
+
+        let mut colors = reader.read_colors(0).map(|colors| colors.into_rgb_f32());
+        let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32());
+
+        let vertices: Vec<Vertex> = positions
+            .map(|[x, y, z]| Vertex {
+                color: colors
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(WHITE, |[r, g, b]| cgmath::Vector3::new(r, g, b)),
+                pos: cgmath::Vector3::new(x, y, z),
+                uv: uvs
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(cgmath::Vector2::new(0.0, 0.0), |[u, v]| {
+                        cgmath::Vector2::new(u, v)
+                    }),
+            })
+            .collect();
+
+        let vertex_count = vertices.len() as u32;
+
+        match reader.read_indices() {
+            Some(indices) => mesh.append(vertices, indices.into_u32()),
+            None => mesh.append(vertices, 0..vertex_count),
+        }
+    }
+
+    Ok(mesh)
+}
+
+fn load_obj(path: &str) -> Result<Mesh> {
+    let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|e| mesh_error(path, &e.to_string()))?;
+
+    let mut mesh = Mesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for model in models {
+        let obj = model.mesh;
+
+        let vertices = obj
+            .positions
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, position)| Vertex {
+                color: obj
+                    .vertex_color
+                    .get(i * 3..i * 3 + 3)
+                    .map_or(WHITE, |c| cgmath::Vector3::new(c[0], c[1], c[2])),
+                pos: cgmath::Vector3::new(position[0], position[1], position[2]),
+                uv: obj
+                    .texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or(cgmath::Vector2::new(0.0, 0.0), |uv| {
+                        cgmath::Vector2::new(uv[0], 1.0 - uv[1])
+                    }),
+            })
+            .collect();
+
+        mesh.append(vertices, obj.indices.into_iter());
+    }
+
+    Ok(mesh)
+}
+
+fn mesh_error(path: &str, message: &str) -> VkcrError {
+    VkcrError::Mesh {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    }
+}
@@ -1,13 +1,31 @@
 pub mod app;
 mod buffers;
+pub mod builder;
 pub mod camera;
 mod commands;
+pub mod compute;
+pub mod controller;
 mod debug;
-mod device;
+pub mod device;
+pub mod draw;
+pub mod dynamic;
+pub mod error;
 mod framebuffer;
 pub mod geometry;
-mod pipeline;
+pub mod golden;
+pub mod headless;
+mod images;
+mod instance;
+pub mod mesh;
+pub mod pipeline;
+pub mod pipeline_cache;
+pub mod reflect;
+mod reload;
+pub mod shader_compiler;
 mod surface;
-mod swapchain;
+pub mod swapchain;
 mod sync;
-mod vertex;
+mod textures;
+pub mod timeline;
+pub mod upload;
+pub mod vertex;
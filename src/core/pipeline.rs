@@ -4,190 +4,628 @@ use ash::vk;
 
 use crate::io::file;
 
+use super::device::DeviceInfo;
+use super::error::{Result, VkcrError};
+use super::images::DEPTH_FORMAT;
+use super::reflect::ShaderLayout;
+use super::swapchain::{ColorOutput, COLOR_OUTPUT_CONSTANT_ID};
 use super::vertex::Vertex;
 
+/// Size of the per-object model matrix pushed to the vertex stage before each draw.
+pub const TRANSFORM_SIZE: u32 = std::mem::size_of::<cgmath::Matrix4<f32>>() as u32;
+
+/// `pipeline[0]` is built from the configured shader and owns the render pass and layout;
+/// material pipelines created with [`PipelineBuilder::build_variant`] follow it.
 #[derive(Clone)]
 pub struct PipelineInfo {
     pub pipeline: Vec<vk::Pipeline>,
     pub pipeline_layout: vk::PipelineLayout,
     pub render_pass: vk::RenderPass,
     pub shader_modules: [vk::ShaderModule; 2],
-    pub render_pass_info: vk::RenderPassCreateInfo,
+    /// What the shaders declare, reflected from their SPIR-V.
+    pub layout: ShaderLayout,
+    /// Samples per pixel of the color and depth attachments.
+    pub samples: vk::SampleCountFlags,
+    /// The color space the fragment shaders write for.
+    pub color_output: ColorOutput,
 }
 
+/// Depth test and write state of a pipeline. Every render pass has a depth attachment, so
+/// disabling both only turns depth sorting off for that pipeline.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+}
 
-pub fn create_pipeline(
-    device: &ash::Device,
-    shader_name: &str,
-    extent: &vk::Extent2D,
-    format: vk::Format,
-    set_layouts: &Vec<vk::DescriptorSetLayout>,
-) -> PipelineInfo {
-    let vert_module =
-        create_shader_pipeline(device, file::read_file(&format!("{shader_name}_v.spv")));
-
-    let frag_module =
-        create_shader_pipeline(device, file::read_file(&format!("{shader_name}_f.spv")));
-
-    let vertex_pipeline_shader_stage_create_info = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::VERTEX)
-        .module(vert_module)
-        .name(CStr::from_bytes_with_nul("main\0".as_bytes()).expect("Failed to convert to cstr"))
-        .build();
-
-    let fragment_pipeline_shader_stage_create_info = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::FRAGMENT)
-        .module(frag_module)
-        .name(CStr::from_bytes_with_nul("main\0".as_bytes()).expect("Failed to convert to cstr"))
-        .build();
-
-    let shader_stages = [
-        vertex_pipeline_shader_stage_create_info,
-        fragment_pipeline_shader_stage_create_info,
-    ];
+impl Default for DepthState {
+    fn default() -> Self {
+        DepthState {
+            test: true,
+            write: true,
+            compare_op: vk::CompareOp::LESS,
+        }
+    }
+}
 
-    let shader_modules = [vert_module, frag_module];
-
-    let pipeline_dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
-        .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
-
-    let (binding_description, attribute_descriptions) = Vertex::get_descriptions();
-
-    let descriptions = [binding_description];
-    let pipeline_vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_attribute_descriptions(&attribute_descriptions)
-        .vertex_binding_descriptions(&descriptions);
-
-    let pipeline_input_assembly_state_create_info =
-        vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false);
-
-    let viewport = vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .height(extent.height as f32)
-        .width(extent.width as f32)
-        .min_depth(0.0)
-        .max_depth(1.0);
-
-    let scissor = vk::Rect2D::builder()
-        .offset(*vk::Offset2D::builder().x(0).y(0))
-        .extent(*extent);
-
-    let scissors = [*scissor];
-    let viewports = [*viewport];
-
-    let pipeline_viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
-        .viewport_count(1)
-        .viewports(&viewports)
-        .scissor_count(1)
-        .scissors(&scissors);
-
-    let pipeline_rasterization_state_create_info =
-        vk::PipelineRasterizationStateCreateInfo::builder()
-            .line_width(1.0)
-            .depth_clamp_enable(false) // TODO: change to true after enabling the GPU feature
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .cull_mode(vk::CullModeFlags::NONE)
-            .front_face(vk::FrontFace::CLOCKWISE)
-            .depth_bias_enable(false);
-
-    let pipeline_multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
-        .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-    let pipeline_color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::builder()
-        .color_write_mask(vk::ColorComponentFlags::RGBA)
-        .blend_enable(true)
-        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-        .color_blend_op(vk::BlendOp::ADD)
-        .src_alpha_blend_factor(vk::BlendFactor::ONE)
-        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-        .alpha_blend_op(vk::BlendOp::ADD);
-
-    let attachments = [*pipeline_color_blend_attachment_state];
-    let pipeline_color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
-        .logic_op_enable(false)
-        .attachments(&attachments);
-
-
-    let pipeline_layout_create_info = if !set_layouts.is_empty() {
-        vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts)
-    } else {
-        vk::PipelineLayoutCreateInfo::builder()
-    };
+/// How a pipeline's output is combined with the color already in the target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the target.
+    Opaque,
+    /// Mixes by the source alpha.
+    #[default]
+    Alpha,
+    /// Adds the color weighted by its alpha, for glows and particles.
+    Additive,
+    /// Source colors are already multiplied by their alpha.
+    Premultiplied,
+}
 
-    let pipeline_layout =
-        unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None) }
-            .expect("Failed to create pipeline layout");
+impl BlendMode {
+    fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => {
+                return *vk::PipelineColorBlendAttachmentState::builder()
+                    .color_write_mask(vk::ColorComponentFlags::RGBA)
+                    .blend_enable(false)
+            }
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+
+        *vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+    }
+}
+
+/// Shaders and fixed-function state of a graphics pipeline. The defaults draw filled triangle
+/// lists without culling, with alpha blending, depth testing and one sample per pixel.
+///
+/// Every material is its own pipeline: describe it here and pass it to
+/// [`super::app::App::create_material`].
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder {
+    /// Path of the shader pair without the `_v.spv`/`_f.spv` suffix.
+    pub shader: String,
+    pub topology: vk::PrimitiveTopology,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub polygon_mode: vk::PolygonMode,
+    pub blend: BlendMode,
+    pub depth: DepthState,
+    /// Ignored by [`PipelineBuilder::build_variant`], which uses the samples of the pipeline it
+    /// shares a render pass with.
+    pub samples: vk::SampleCountFlags,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        PipelineBuilder::new("assets/shaders/default")
+    }
+}
+
+impl PipelineBuilder {
+    pub fn new(shader: &str) -> Self {
+        PipelineBuilder {
+            shader: shader.to_owned(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            polygon_mode: vk::PolygonMode::FILL,
+            blend: BlendMode::Alpha,
+            depth: DepthState::default(),
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn shader(mut self, shader: &str) -> Self {
+        self.shader = shader.to_owned();
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Draws only the edges of the triangles.
+    pub fn wireframe(self) -> Self {
+        self.polygon_mode(vk::PolygonMode::LINE)
+    }
+
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Multisampling; more than one sample renders into an intermediate target that is
+    /// resolved into the final image.
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Builds a pipeline with its own render pass and layout, rendering into images of
+    /// `format` that end up in `final_layout`. The fragment shader learns `color_output`
+    /// through [`COLOR_OUTPUT_CONSTANT_ID`].
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn build(
+        &self,
+        device_info: &DeviceInfo,
+        pipeline_cache: vk::PipelineCache,
+        extent: &vk::Extent2D,
+        format: vk::Format,
+        color_output: ColorOutput,
+        final_layout: vk::ImageLayout,
+        set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<PipelineInfo> {
+        self.check_support(device_info)?;
+
+        let device = &device_info.device;
+        let layout = self.load_layout()?;
+
+        let pipeline_layout_create_info = if !set_layouts.is_empty() {
+            vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(set_layouts)
+                .push_constant_ranges(&layout.push_constants)
+        } else {
+            vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&layout.push_constants)
+        };
+
+        let pipeline_layout =
+            unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None) }?;
+
+        // Whatever was created before a failure is destroyed again, so a bad shader edit
+        // doesn't leak on every reload.
+        let render_pass = match create_render_pass(device, format, final_layout, self.samples) {
+            Ok(render_pass) => render_pass,
+            Err(e) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                return Err(e);
+            }
+        };
+
+        let (pipeline, shader_modules) = match self.create(
+            device,
+            pipeline_cache,
+            extent,
+            pipeline_layout,
+            render_pass,
+            self.samples,
+            color_output,
+        ) {
+            Ok(created) => created,
+            Err(e) => {
+                unsafe {
+                    device.destroy_render_pass(render_pass, None);
+                    device.destroy_pipeline_layout(pipeline_layout, None);
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(PipelineInfo {
+            pipeline: vec![pipeline],
+            pipeline_layout,
+            render_pass,
+            shader_modules,
+            layout,
+            samples: self.samples,
+            color_output,
+        })
+    }
+
+    /// Builds a pipeline that renders in `base`'s render pass and binds `base`'s descriptor
+    /// sets and push constants, so its shaders may only use resources `base` declares.
+    pub(crate) fn build_variant(
+        &self,
+        device_info: &DeviceInfo,
+        pipeline_cache: vk::PipelineCache,
+        extent: &vk::Extent2D,
+        base: &PipelineInfo,
+    ) -> Result<vk::Pipeline> {
+        self.check_support(device_info)?;
+
+        let device = &device_info.device;
+        self.load_layout()?
+            .check_compatible(&base.layout)
+            .map_err(|message| VkcrError::Shader {
+                path: self.shader.clone(),
+                message,
+            })?;
+
+        let (pipeline, shader_modules) = self.create(
+            device,
+            pipeline_cache,
+            extent,
+            base.pipeline_layout,
+            base.render_pass,
+            base.samples,
+            base.color_output,
+        )?;
+
+        // The pipeline keeps its own copy of the code.
+        for shader_module in shader_modules {
+            unsafe { device.destroy_shader_module(shader_module, None) };
+        }
+
+        Ok(pipeline)
+    }
+
+    fn check_support(&self, device_info: &DeviceInfo) -> Result<()> {
+        if self.polygon_mode != vk::PolygonMode::FILL
+            && device_info.features.fill_mode_non_solid == vk::FALSE
+        {
+            return Err(VkcrError::Unsupported(format!(
+                "Polygon mode {:?}",
+                self.polygon_mode
+            )));
+        }
+
+        let limits = device_info
+            .logical_devices
+            .first()
+            .expect("Failed to get first logical device")
+            .properties
+            .limits;
+        let sample_counts =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        if !sample_counts.contains(self.samples) {
+            return Err(VkcrError::Unsupported(format!(
+                "Sample count {:?}",
+                self.samples
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Reflects the shaders and checks their inputs against [`Vertex`].
+    fn load_layout(&self) -> Result<ShaderLayout> {
+        let layout = ShaderLayout::load(&self.shader)?;
+
+        let (_, attribute_descriptions) = Vertex::get_descriptions();
+        layout
+            .validate_vertex_input(&attribute_descriptions)
+            .map_err(|message| VkcrError::Shader {
+                path: format!("{}_v.spv", self.shader),
+                message,
+            })?;
+
+        Ok(layout)
+    }
+
+    /// Creates the pipeline and its shader modules, which are destroyed again if it fails.
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        &self,
+        device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
+        extent: &vk::Extent2D,
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlags,
+        color_output: ColorOutput,
+    ) -> Result<(vk::Pipeline, [vk::ShaderModule; 2])> {
+        let vert_module = create_shader_pipeline(device, &format!("{}_v.spv", self.shader))?;
+
+        let frag_module = match create_shader_pipeline(device, &format!("{}_f.spv", self.shader)) {
+            Ok(frag_module) => frag_module,
+            Err(e) => {
+                unsafe { device.destroy_shader_module(vert_module, None) };
+                return Err(e);
+            }
+        };
+
+        let vertex_pipeline_shader_stage_create_info =
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert_module)
+                .name(
+                    CStr::from_bytes_with_nul("main\0".as_bytes())
+                        .expect("Failed to convert to cstr"),
+                )
+                .build();
+
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: COLOR_OUTPUT_CONSTANT_ID,
+            offset: 0,
+            size: std::mem::size_of::<u32>(),
+        }];
+        let specialization_data = color_output.shader_value().to_ne_bytes();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data);
+
+        let fragment_pipeline_shader_stage_create_info =
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag_module)
+                .name(
+                    CStr::from_bytes_with_nul("main\0".as_bytes())
+                        .expect("Failed to convert to cstr"),
+                )
+                .specialization_info(&specialization_info)
+                .build();
+
+        let shader_stages = [
+            vertex_pipeline_shader_stage_create_info,
+            fragment_pipeline_shader_stage_create_info,
+        ];
+
+        let shader_modules = [vert_module, frag_module];
+
+        let pipeline_dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        let (binding_description, attribute_descriptions) = Vertex::get_descriptions();
+
+        let descriptions = [binding_description];
+        let pipeline_vertex_input_state_create_info =
+            vk::PipelineVertexInputStateCreateInfo::builder()
+                .vertex_attribute_descriptions(&attribute_descriptions)
+                .vertex_binding_descriptions(&descriptions);
+
+        let pipeline_input_assembly_state_create_info =
+            vk::PipelineInputAssemblyStateCreateInfo::builder()
+                .topology(self.topology)
+                .primitive_restart_enable(false);
+
+        let viewport = vk::Viewport::builder()
+            .x(0.0)
+            .y(0.0)
+            .height(extent.height as f32)
+            .width(extent.width as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+
+        let scissor = vk::Rect2D::builder()
+            .offset(*vk::Offset2D::builder().x(0).y(0))
+            .extent(*extent);
+
+        let scissors = [*scissor];
+        let viewports = [*viewport];
+
+        let pipeline_viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .viewports(&viewports)
+            .scissor_count(1)
+            .scissors(&scissors);
+
+        let pipeline_rasterization_state_create_info =
+            vk::PipelineRasterizationStateCreateInfo::builder()
+                .line_width(1.0)
+                .depth_clamp_enable(false) // TODO: change to true after enabling the GPU feature
+                .rasterizer_discard_enable(false)
+                .polygon_mode(self.polygon_mode)
+                .cull_mode(self.cull_mode)
+                .front_face(self.front_face)
+                .depth_bias_enable(false);
+
+        let pipeline_multisample_state_create_info =
+            vk::PipelineMultisampleStateCreateInfo::builder()
+                .sample_shading_enable(false)
+                .rasterization_samples(samples);
+
+        let attachments = [self.blend.attachment_state()];
+        let pipeline_color_blend_state_create_info =
+            vk::PipelineColorBlendStateCreateInfo::builder()
+                .logic_op_enable(false)
+                .attachments(&attachments);
+
+        let pipeline_depth_stencil_state_create_info =
+            vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(self.depth.test)
+                .depth_write_enable(self.depth.write)
+                .depth_compare_op(self.depth.compare_op)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false);
+
+        let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&pipeline_vertex_input_state_create_info)
+            .input_assembly_state(&pipeline_input_assembly_state_create_info)
+            .viewport_state(&pipeline_viewport_state_create_info)
+            .rasterization_state(&pipeline_rasterization_state_create_info)
+            .multisample_state(&pipeline_multisample_state_create_info)
+            .color_blend_state(&pipeline_color_blend_state_create_info)
+            .depth_stencil_state(&pipeline_depth_stencil_state_create_info)
+            .dynamic_state(&pipeline_dynamic_state_create_info)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = match unsafe {
+            device.create_graphics_pipelines(pipeline_cache, &[*pipeline_create_info], None)
+        } {
+            Ok(pipeline) => pipeline,
+            Err((_, result)) => {
+                for shader_module in shader_modules {
+                    unsafe { device.destroy_shader_module(shader_module, None) };
+                }
+                return Err(result.into());
+            }
+        };
+
+        Ok((pipeline[0], shader_modules))
+    }
+}
+
+/// A color and a depth attachment. With more than one sample the color attachment is an
+/// intermediate image that is resolved into a third attachment, the final target.
+fn create_render_pass(
+    device: &ash::Device,
+    format: vk::Format,
+    final_layout: vk::ImageLayout,
+    samples: vk::SampleCountFlags,
+) -> Result<vk::RenderPass> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
 
     let attachment_description = vk::AttachmentDescription::builder()
         .format(format)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(if multisampled {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
+        .final_layout(if multisampled {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            final_layout
+        });
+
+    let depth_attachment_description = vk::AttachmentDescription::builder()
+        .format(DEPTH_FORMAT)
+        .samples(samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let resolve_attachment_description = vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
         .store_op(vk::AttachmentStoreOp::STORE)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(final_layout);
 
     let attachment_reference = vk::AttachmentReference::builder()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
+    let depth_attachment_reference = vk::AttachmentReference::builder()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let resolve_attachment_reference = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
     let color_attachments = [*attachment_reference];
-    let subpass_description =
-        vk::SubpassDescription::builder().color_attachments(&color_attachments);
+    let resolve_attachments = [*resolve_attachment_reference];
+    let mut subpass_description = vk::SubpassDescription::builder()
+        .color_attachments(&color_attachments)
+        .depth_stencil_attachment(&depth_attachment_reference);
+    if multisampled {
+        subpass_description = subpass_description.resolve_attachments(&resolve_attachments);
+    }
 
+    // Every frame in flight renders to the same depth image and multisampled color target, so
+    // clearing them has to wait for the depth and color writes of the previous frame.
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        )
+        .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )
+        .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )
+        .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        );
 
     let subpass_descriptions = [*subpass_description];
-    let attachments = [*attachment_description];
+    let all_attachments = [
+        *attachment_description,
+        *depth_attachment_description,
+        *resolve_attachment_description,
+    ];
+    let attachments = if multisampled {
+        &all_attachments[..]
+    } else {
+        &all_attachments[..2]
+    };
     let dependencies = [*dependency];
     let render_pass_info = vk::RenderPassCreateInfo::builder()
-        .attachments(&attachments)
+        .attachments(attachments)
         .subpasses(&subpass_descriptions)
         .dependencies(&dependencies);
 
-    let render_pass = unsafe { device.create_render_pass(&render_pass_info, None) }
-        .expect("Failed to create render pass");
-
-    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
-        .stages(&shader_stages)
-        .vertex_input_state(&pipeline_vertex_input_state_create_info)
-        .input_assembly_state(&pipeline_input_assembly_state_create_info)
-        .viewport_state(&pipeline_viewport_state_create_info)
-        .rasterization_state(&pipeline_rasterization_state_create_info)
-        .multisample_state(&pipeline_multisample_state_create_info)
-        .color_blend_state(&pipeline_color_blend_state_create_info)
-        .dynamic_state(&pipeline_dynamic_state_create_info)
-        .layout(pipeline_layout)
-        .render_pass(render_pass)
-        .subpass(0);
-
-    let pipeline = unsafe {
-        device.create_graphics_pipelines(vk::PipelineCache::null(), &[*pipeline_create_info], None)
-    }
-    .expect("Failed to create pipeline");
-
-    PipelineInfo {
-        pipeline,
-        pipeline_layout,
-        render_pass,
-        shader_modules,
-        render_pass_info: *render_pass_info,
-    }
+    Ok(unsafe { device.create_render_pass(&render_pass_info, None) }?)
 }
 
-pub fn create_shader_pipeline(device: &ash::Device, code: Vec<u8>) -> vk::ShaderModule {
-    let shader_module_create_info =
-        vk::ShaderModuleCreateInfo::builder().code(unsafe { code.align_to::<u32>().1 });
-    unsafe { device.create_shader_module(&shader_module_create_info, None) }
-        .expect("Failed to create shader module")
+pub fn create_shader_pipeline(device: &ash::Device, path: &str) -> Result<vk::ShaderModule> {
+    let code =
+        ash::util::read_spv(&mut std::io::Cursor::new(file::read_file(path)?)).map_err(|e| {
+            VkcrError::Shader {
+                path: path.to_owned(),
+                message: e.to_string(),
+            }
+        })?;
+
+    let shader_module_create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    Ok(unsafe { device.create_shader_module(&shader_module_create_info, None) }?)
+}
+
+/// Destroys everything [`PipelineBuilder::build`] created, along with the material pipelines
+/// in `pipeline`. None of them may still be in use.
+pub fn destroy_pipeline(device: &ash::Device, pipeline_info: &PipelineInfo) {
+    for pipeline in &pipeline_info.pipeline {
+        unsafe { device.destroy_pipeline(*pipeline, None) };
+    }
+
+    for shader_module in pipeline_info.shader_modules {
+        unsafe { device.destroy_shader_module(shader_module, None) };
+    }
+
+    unsafe { device.destroy_pipeline_layout(pipeline_info.pipeline_layout, None) };
+
+    unsafe { device.destroy_render_pass(pipeline_info.render_pass, None) };
 }
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use super::device::DeviceInfo;
+use super::error::{Result, VkcrError};
+
+const CACHE_FILE: &str = "pipeline_cache.bin";
+
+/// Size of `VkPipelineCacheHeaderVersionOne`: header size, header version, vendor ID, device ID
+/// and the 16 byte pipeline cache UUID.
+const HEADER_SIZE: usize = 32;
+
+/// Where the pipeline cache of `cache_dir` is stored.
+pub fn cache_path(cache_dir: &str) -> PathBuf {
+    Path::new(cache_dir).join(CACHE_FILE)
+}
+
+/// Whether `data` starts with a pipeline cache header written by the device described by
+/// `properties`. Drivers may reject or even crash on data from another device or driver
+/// version, so anything else is thrown away.
+pub fn header_matches(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let word = |offset: usize| {
+        u32::from_le_bytes(
+            data[offset..offset + 4]
+                .try_into()
+                .expect("Failed to read header word"),
+        )
+    };
+
+    word(0) as usize >= HEADER_SIZE
+        && word(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && word(8) == properties.vendor_id
+        && word(12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+/// Creates a pipeline cache, seeded with the blob at `path` if it was written by the same
+/// device and driver. Without a path, or with a missing or stale file, the cache starts empty.
+pub(crate) fn create_pipeline_cache(
+    device_info: &DeviceInfo,
+    path: Option<&Path>,
+) -> Result<vk::PipelineCache> {
+    let properties = &device_info
+        .logical_devices
+        .first()
+        .expect("Failed to get first logical device")
+        .properties;
+
+    let data = match path.map(|path| (path, std::fs::read(path))) {
+        Some((path, Ok(data))) if header_matches(&data, properties) => {
+            debug!("Loaded pipeline cache from {}", path.display());
+            data
+        }
+        Some((path, Ok(_))) => {
+            info!(
+                "Ignoring pipeline cache {} from another device or driver",
+                path.display()
+            );
+            Vec::new()
+        }
+        _ => Vec::new(),
+    };
+
+    let pipeline_cache_create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&data);
+
+    let pipeline_cache = unsafe {
+        device_info
+            .device
+            .create_pipeline_cache(&pipeline_cache_create_info, None)
+    }?;
+
+    Ok(pipeline_cache)
+}
+
+/// Writes the contents of `pipeline_cache` to `path`, creating its directory if needed.
+pub(crate) fn save_pipeline_cache(
+    device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    path: &Path,
+) -> Result<()> {
+    let data = unsafe { device.get_pipeline_cache_data(pipeline_cache) }?;
+
+    let io_error = |source| VkcrError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(io_error)?;
+    }
+    std::fs::write(path, data).map_err(io_error)?;
+
+    debug!("Saved pipeline cache to {}", path.display());
+
+    Ok(())
+}
@@ -0,0 +1,654 @@
+use std::collections::HashMap;
+
+use ash::vk;
+use rspirv::dr::{Instruction, Module, Operand};
+use rspirv::spirv::{Decoration, Dim, ExecutionModel, Op, StorageClass, Word};
+
+use crate::io::file;
+
+use super::error::{Result, VkcrError};
+
+/// A resource the shaders read through a descriptor set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Number of array elements, 1 for a plain resource.
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+/// A `layout(location = N) in` variable of a vertex shader.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub format: vk::Format,
+    /// The variable name, empty when the SPIR-V was stripped of debug names.
+    pub name: String,
+}
+
+/// The resources a single SPIR-V module declares.
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    /// The byte range of the push constant block, if the shader declares one.
+    pub push_constants: Option<vk::PushConstantRange>,
+    /// Only filled for vertex shaders, sorted by location.
+    pub inputs: Vec<VertexInput>,
+}
+
+/// The combined interface of the stages of a pipeline: what its descriptor set layouts and
+/// pipeline layout need to contain, and what it expects from the vertex buffer.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderLayout {
+    /// The shader the layout was loaded from, for error messages.
+    pub name: String,
+    /// Sorted by set and binding. A binding used by several stages appears once.
+    pub bindings: Vec<DescriptorBinding>,
+    /// One range per stage that declares a push constant block.
+    pub push_constants: Vec<vk::PushConstantRange>,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+impl ShaderLayout {
+    /// Reflects `<shader>_v.spv` and `<shader>_f.spv`, the pair [`super::pipeline`] loads.
+    pub fn load(shader_name: &str) -> Result<Self> {
+        Self::load_stages(shader_name, &["v", "f"])
+    }
+
+    /// Reflects `<shader>_c.spv`, the compute shader [`super::compute`] loads.
+    pub fn load_compute(shader_name: &str) -> Result<Self> {
+        Self::load_stages(shader_name, &["c"])
+    }
+
+    fn load_stages(shader_name: &str, stages: &[&str]) -> Result<Self> {
+        let stages = stages
+            .iter()
+            .map(|stage| {
+                let path = format!("{shader_name}_{stage}.spv");
+                let code = ash::util::read_spv(&mut std::io::Cursor::new(file::read_file(&path)?))
+                    .map_err(|e| e.to_string())
+                    .and_then(|code| ShaderReflection::from_spirv(&code));
+
+                code.map_err(|message| VkcrError::Shader { path, message })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut layout = Self::from_stages(&stages).map_err(|message| VkcrError::Shader {
+            path: shader_name.to_owned(),
+            message,
+        })?;
+        layout.name = shader_name.to_owned();
+        Ok(layout)
+    }
+
+    /// Merges the stages of one pipeline. Fails when two stages declare the same binding with
+    /// different types.
+    pub fn from_stages(stages: &[ShaderReflection]) -> std::result::Result<Self, String> {
+        let mut layout = ShaderLayout::default();
+
+        for stage in stages {
+            for binding in &stage.bindings {
+                match layout
+                    .bindings
+                    .iter_mut()
+                    .find(|b| b.set == binding.set && b.binding == binding.binding)
+                {
+                    Some(existing) if existing.descriptor_type != binding.descriptor_type => {
+                        return Err(format!(
+                            "set {} binding {} is a {:?} in one stage and a {:?} in another",
+                            binding.set,
+                            binding.binding,
+                            existing.descriptor_type,
+                            binding.descriptor_type
+                        ));
+                    }
+                    Some(existing) => {
+                        existing.stages |= binding.stages;
+                        existing.count = existing.count.max(binding.count);
+                    }
+                    None => layout.bindings.push(*binding),
+                }
+            }
+
+            layout.push_constants.extend(stage.push_constants);
+
+            if stage.stage == vk::ShaderStageFlags::VERTEX {
+                layout.vertex_inputs = stage.inputs.clone();
+            }
+        }
+
+        layout.bindings.sort_by_key(|b| (b.set, b.binding));
+        Ok(layout)
+    }
+
+    /// The bindings of descriptor set `set`, ready for `vkCreateDescriptorSetLayout`.
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding> {
+        self.bindings
+            .iter()
+            .filter(|b| b.set == set)
+            .map(|b| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(b.binding)
+                    .descriptor_type(b.descriptor_type)
+                    .descriptor_count(b.count)
+                    .stage_flags(b.stages)
+            })
+            .collect()
+    }
+
+    /// The stages whose push constant ranges overlap `offset..offset + size`, which is what
+    /// `vkCmdPushConstants` has to be called with. Empty when no range covers all of it.
+    pub fn push_constant_stages(&self, offset: u32, size: u32) -> vk::ShaderStageFlags {
+        let covered = self
+            .push_constants
+            .iter()
+            .any(|r| r.offset <= offset && offset + size <= r.offset + r.size);
+        if !covered {
+            return vk::ShaderStageFlags::empty();
+        }
+
+        self.push_constants
+            .iter()
+            .filter(|r| r.offset < offset + size && offset < r.offset + r.size)
+            .fold(vk::ShaderStageFlags::empty(), |stages, r| {
+                stages | r.stage_flags
+            })
+    }
+
+    /// Checks that a pipeline with this layout can bind `base`'s descriptor sets and push
+    /// constants: every binding must exist in `base` with the same type and be visible to the
+    /// stages using it, and every push constant range must lie within one of `base`'s.
+    pub fn check_compatible(&self, base: &ShaderLayout) -> std::result::Result<(), String> {
+        for binding in &self.bindings {
+            let provided = base.bindings.iter().find(|b| {
+                b.set == binding.set
+                    && b.binding == binding.binding
+                    && b.descriptor_type == binding.descriptor_type
+                    && b.count >= binding.count
+                    && b.stages.contains(binding.stages)
+            });
+            if provided.is_none() {
+                return Err(format!(
+                    "set {} binding {} ({:?}) is not provided by {}",
+                    binding.set, binding.binding, binding.descriptor_type, base.name
+                ));
+            }
+        }
+
+        for range in &self.push_constants {
+            let covered = base.push_constants.iter().any(|r| {
+                r.stage_flags.contains(range.stage_flags)
+                    && r.offset <= range.offset
+                    && range.offset + range.size <= r.offset + r.size
+            });
+            if !covered {
+                return Err(format!(
+                    "push constants {}..{} of {:?} are not provided by {}",
+                    range.offset,
+                    range.offset + range.size,
+                    range.stage_flags,
+                    base.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every vertex shader input is fed by an attribute with the same kind and
+    /// width of components. The shader may read fewer or more 32-bit components than the
+    /// attribute has, as Vulkan drops or fills those in, but missing 64-bit ones are not filled.
+    pub fn validate_vertex_input(
+        &self,
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> std::result::Result<(), String> {
+        for input in &self.vertex_inputs {
+            let name = if input.name.is_empty() {
+                "input".to_owned()
+            } else {
+                format!("`{}`", input.name)
+            };
+
+            let Some(attribute) = attributes.iter().find(|a| a.location == input.location) else {
+                return Err(format!(
+                    "the vertex shader reads {name} at location {}, but the vertex type has no attribute there",
+                    input.location
+                ));
+            };
+
+            if let (Some(shader), Some(vertex)) =
+                (format_info(input.format), format_info(attribute.format))
+            {
+                let (shader_kind, shader_width, shader_count) = shader;
+                let (vertex_kind, vertex_width, vertex_count) = vertex;
+                if shader_kind != vertex_kind
+                    || shader_width != vertex_width
+                    || (shader_width == 64 && shader_count > vertex_count)
+                {
+                    return Err(format!(
+                        "the vertex shader reads {name} at location {} as {:?}, but the vertex type provides {:?}",
+                        input.location, input.format, attribute.format
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ShaderReflection {
+    pub fn from_spirv(code: &[u32]) -> std::result::Result<Self, String> {
+        let module = rspirv::dr::load_words(code).map_err(|e| e.to_string())?;
+        let reflector = Reflector::new(&module);
+
+        let entry_point = module.entry_points.first().ok_or("no entry point")?;
+        let stage = match entry_point.operands.first() {
+            Some(Operand::ExecutionModel(model)) => stage_flags(*model),
+            _ => None,
+        }
+        .ok_or("unsupported execution model")?;
+
+        let mut reflection = ShaderReflection {
+            stage,
+            bindings: Vec::new(),
+            push_constants: None,
+            inputs: Vec::new(),
+        };
+
+        for variable in module
+            .types_global_values
+            .iter()
+            .filter(|inst| inst.class.opcode == Op::Variable)
+        {
+            let (Some(id), Some(pointer)) = (variable.result_id, variable.result_type) else {
+                continue;
+            };
+            let Some(Operand::StorageClass(storage)) = variable.operands.first() else {
+                continue;
+            };
+            let pointee = reflector
+                .pointee(pointer)
+                .ok_or("variable without pointer type")?;
+
+            match storage {
+                StorageClass::Uniform
+                | StorageClass::UniformConstant
+                | StorageClass::StorageBuffer => {
+                    let (descriptor_type, count) = reflector.descriptor(*storage, pointee)?;
+                    reflection.bindings.push(DescriptorBinding {
+                        set: reflector
+                            .decoration(id, Decoration::DescriptorSet)
+                            .unwrap_or(0),
+                        binding: reflector.decoration(id, Decoration::Binding).unwrap_or(0),
+                        descriptor_type,
+                        count,
+                        stages: stage,
+                    });
+                }
+
+                StorageClass::PushConstant => {
+                    let (start, end) = reflector.member_range(pointee);
+                    reflection.push_constants = Some(
+                        *vk::PushConstantRange::builder()
+                            .stage_flags(stage)
+                            .offset(start)
+                            .size(end - start),
+                    );
+                }
+
+                StorageClass::Input if stage == vk::ShaderStageFlags::VERTEX => {
+                    if reflector.has_decoration(id, Decoration::BuiltIn) {
+                        continue;
+                    }
+                    let location = reflector
+                        .decoration(id, Decoration::Location)
+                        .ok_or("vertex input without location")?;
+                    let name = reflector.names.get(&id).cloned().unwrap_or_default();
+
+                    let mut location = location;
+                    for format in reflector.input_formats(pointee)? {
+                        reflection.inputs.push(VertexInput {
+                            location,
+                            format,
+                            name: name.clone(),
+                        });
+                        location += format_locations(format);
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+        reflection.inputs.sort_by_key(|input| input.location);
+        Ok(reflection)
+    }
+}
+
+fn stage_flags(model: ExecutionModel) -> Option<vk::ShaderStageFlags> {
+    Some(match model {
+        ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+        ExecutionModel::TessellationControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        ExecutionModel::TessellationEvaluation => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        ExecutionModel::Geometry => vk::ShaderStageFlags::GEOMETRY,
+        ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+        ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ComponentKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+/// The vertex formats reflection produces, by component kind, bit width and count.
+const VERTEX_FORMATS: [(ComponentKind, u32, u32, vk::Format); 16] = [
+    (ComponentKind::Float, 32, 1, vk::Format::R32_SFLOAT),
+    (ComponentKind::Float, 32, 2, vk::Format::R32G32_SFLOAT),
+    (ComponentKind::Float, 32, 3, vk::Format::R32G32B32_SFLOAT),
+    (ComponentKind::Float, 32, 4, vk::Format::R32G32B32A32_SFLOAT),
+    (ComponentKind::Float, 64, 1, vk::Format::R64_SFLOAT),
+    (ComponentKind::Float, 64, 2, vk::Format::R64G64_SFLOAT),
+    (ComponentKind::Float, 64, 3, vk::Format::R64G64B64_SFLOAT),
+    (ComponentKind::Float, 64, 4, vk::Format::R64G64B64A64_SFLOAT),
+    (ComponentKind::Sint, 32, 1, vk::Format::R32_SINT),
+    (ComponentKind::Sint, 32, 2, vk::Format::R32G32_SINT),
+    (ComponentKind::Sint, 32, 3, vk::Format::R32G32B32_SINT),
+    (ComponentKind::Sint, 32, 4, vk::Format::R32G32B32A32_SINT),
+    (ComponentKind::Uint, 32, 1, vk::Format::R32_UINT),
+    (ComponentKind::Uint, 32, 2, vk::Format::R32G32_UINT),
+    (ComponentKind::Uint, 32, 3, vk::Format::R32G32B32_UINT),
+    (ComponentKind::Uint, 32, 4, vk::Format::R32G32B32A32_UINT),
+];
+
+/// Component kind, bit width and count of `format`, if it is one of [`VERTEX_FORMATS`].
+fn format_info(format: vk::Format) -> Option<(ComponentKind, u32, u32)> {
+    VERTEX_FORMATS
+        .iter()
+        .find(|(.., f)| *f == format)
+        .map(|(kind, width, count, _)| (*kind, *width, *count))
+}
+
+/// Number of input locations an attribute of `format` takes. A location holds 128 bits, so
+/// three or four 64-bit components need two.
+fn format_locations(format: vk::Format) -> u32 {
+    match format_info(format) {
+        Some((_, 64, count)) if count > 2 => 2,
+        _ => 1,
+    }
+}
+
+fn vertex_format(kind: ComponentKind, width: u32, count: u32) -> Option<vk::Format> {
+    VERTEX_FORMATS
+        .iter()
+        .find(|(k, w, c, _)| (*k, *w, *c) == (kind, width, count))
+        .map(|(.., format)| *format)
+}
+
+/// Lookup tables over the declarations of a module.
+struct Reflector<'a> {
+    types: HashMap<Word, &'a Instruction>,
+    decorations: HashMap<Word, Vec<&'a [Operand]>>,
+    member_decorations: HashMap<(Word, u32), Vec<&'a [Operand]>>,
+    names: HashMap<Word, String>,
+}
+
+impl<'a> Reflector<'a> {
+    fn new(module: &'a Module) -> Self {
+        let mut reflector = Reflector {
+            types: HashMap::new(),
+            decorations: HashMap::new(),
+            member_decorations: HashMap::new(),
+            names: HashMap::new(),
+        };
+
+        for inst in &module.types_global_values {
+            if let Some(id) = inst.result_id {
+                reflector.types.insert(id, inst);
+            }
+        }
+
+        for inst in &module.annotations {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (Op::Decorate, [Operand::IdRef(target), rest @ ..]) => {
+                    reflector.decorations.entry(*target).or_default().push(rest);
+                }
+                (
+                    Op::MemberDecorate,
+                    [Operand::IdRef(target), Operand::LiteralInt32(member), rest @ ..],
+                ) => {
+                    reflector
+                        .member_decorations
+                        .entry((*target, *member))
+                        .or_default()
+                        .push(rest);
+                }
+                _ => (),
+            }
+        }
+
+        for inst in &module.debug_names {
+            if let (Op::Name, [Operand::IdRef(target), Operand::LiteralString(name)]) =
+                (inst.class.opcode, inst.operands.as_slice())
+            {
+                reflector.names.insert(*target, name.clone());
+            }
+        }
+
+        reflector
+    }
+
+    fn has_decoration(&self, id: Word, decoration: Decoration) -> bool {
+        find_decoration(self.decorations.get(&id), decoration).is_some()
+    }
+
+    /// The literal argument of `decoration` on `id`.
+    fn decoration(&self, id: Word, decoration: Decoration) -> Option<u32> {
+        literal(find_decoration(self.decorations.get(&id), decoration)?)
+    }
+
+    fn member_decoration(&self, id: Word, member: u32, decoration: Decoration) -> Option<u32> {
+        literal(find_decoration(
+            self.member_decorations.get(&(id, member)),
+            decoration,
+        )?)
+    }
+
+    fn opcode(&self, id: Word) -> Option<Op> {
+        self.types.get(&id).map(|inst| inst.class.opcode)
+    }
+
+    fn id_operand(&self, id: Word, index: usize) -> Option<Word> {
+        match self.types.get(&id)?.operands.get(index)? {
+            Operand::IdRef(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn literal_operand(&self, id: Word, index: usize) -> Option<u32> {
+        match self.types.get(&id)?.operands.get(index)? {
+            Operand::LiteralInt32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn pointee(&self, pointer: Word) -> Option<Word> {
+        (self.opcode(pointer)? == Op::TypePointer)
+            .then(|| self.id_operand(pointer, 1))
+            .flatten()
+    }
+
+    /// Strips arrays from `ty`, returning the element type and the number of elements.
+    fn array_element(&self, ty: Word) -> (Word, u32) {
+        match self.opcode(ty) {
+            Some(Op::TypeArray) => {
+                let length = self
+                    .id_operand(ty, 1)
+                    .and_then(|constant| self.literal_operand(constant, 0))
+                    .unwrap_or(1);
+                let element = self.id_operand(ty, 0).unwrap_or(ty);
+                let (element, count) = self.array_element(element);
+                (element, count * length)
+            }
+            Some(Op::TypeRuntimeArray) => (self.id_operand(ty, 0).unwrap_or(ty), 1),
+            _ => (ty, 1),
+        }
+    }
+
+    fn descriptor(
+        &self,
+        storage: StorageClass,
+        ty: Word,
+    ) -> std::result::Result<(vk::DescriptorType, u32), String> {
+        let (element, count) = self.array_element(ty);
+
+        let descriptor_type = match self.opcode(element) {
+            Some(Op::TypeStruct)
+                if storage == StorageClass::StorageBuffer
+                    || self.has_decoration(element, Decoration::BufferBlock) =>
+            {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            Some(Op::TypeStruct) => vk::DescriptorType::UNIFORM_BUFFER,
+            Some(Op::TypeSampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            Some(Op::TypeSampler) => vk::DescriptorType::SAMPLER,
+            Some(Op::TypeAccelerationStructureKHR) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            Some(Op::TypeImage) => {
+                let dim = match self.types[&element].operands.get(1) {
+                    Some(Operand::Dim(dim)) => *dim,
+                    _ => Dim::Dim2D,
+                };
+                let storage_image = self.literal_operand(element, 5) == Some(2);
+
+                match (dim, storage_image) {
+                    (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (Dim::DimBuffer, true) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (Dim::DimBuffer, false) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (_, true) => vk::DescriptorType::STORAGE_IMAGE,
+                    (_, false) => vk::DescriptorType::SAMPLED_IMAGE,
+                }
+            }
+            other => return Err(format!("unsupported resource type {other:?}")),
+        };
+
+        Ok((descriptor_type, count))
+    }
+
+    /// The bytes a struct's members occupy, from the lowest member offset to the end of the
+    /// last member.
+    fn member_range(&self, ty: Word) -> (u32, u32) {
+        let members = self.types.get(&ty).map_or(0, |inst| inst.operands.len());
+        let mut range: Option<(u32, u32)> = None;
+
+        for member in 0..members {
+            let Some(member_type) = self.id_operand(ty, member) else {
+                continue;
+            };
+            let offset = self
+                .member_decoration(ty, member as u32, Decoration::Offset)
+                .unwrap_or(0);
+            let end = offset + self.size(member_type, Some((ty, member as u32)));
+            range = Some(range.map_or((offset, end), |(start, last)| {
+                (start.min(offset), last.max(end))
+            }));
+        }
+
+        range.unwrap_or((0, 0))
+    }
+
+    /// Size in bytes of `ty`. Matrix strides are decorations on the struct member that holds
+    /// the matrix, so `member` names it when there is one.
+    fn size(&self, ty: Word, member: Option<(Word, u32)>) -> u32 {
+        match self.opcode(ty) {
+            Some(Op::TypeInt | Op::TypeFloat) => self.literal_operand(ty, 0).unwrap_or(32) / 8,
+            Some(Op::TypeVector) => {
+                let component = self.id_operand(ty, 0).map_or(4, |c| self.size(c, None));
+                component * self.literal_operand(ty, 1).unwrap_or(1)
+            }
+            Some(Op::TypeMatrix) => {
+                let columns = self.literal_operand(ty, 1).unwrap_or(1);
+                let stride = member
+                    .and_then(|(s, m)| self.member_decoration(s, m, Decoration::MatrixStride))
+                    .or_else(|| self.id_operand(ty, 0).map(|column| self.size(column, None)))
+                    .unwrap_or(16);
+                columns * stride
+            }
+            Some(Op::TypeArray) => {
+                let length = self
+                    .id_operand(ty, 1)
+                    .and_then(|constant| self.literal_operand(constant, 0))
+                    .unwrap_or(1);
+                let stride = self
+                    .decoration(ty, Decoration::ArrayStride)
+                    .unwrap_or_else(|| {
+                        self.id_operand(ty, 0)
+                            .map_or(0, |element| self.size(element, member))
+                    });
+                length * stride
+            }
+            Some(Op::TypeStruct) => self.member_range(ty).1,
+            _ => 0,
+        }
+    }
+
+    /// Vertex formats for an input of type `ty`, one per matrix column. Each starts at the
+    /// location after the previous one ends, see [`format_locations`].
+    fn input_formats(&self, ty: Word) -> std::result::Result<Vec<vk::Format>, String> {
+        let (columns, column) = match self.opcode(ty) {
+            Some(Op::TypeMatrix) => (
+                self.literal_operand(ty, 1).unwrap_or(1),
+                self.id_operand(ty, 0).unwrap_or(ty),
+            ),
+            _ => (1, ty),
+        };
+
+        let (scalar, count) = match self.opcode(column) {
+            Some(Op::TypeVector) => (
+                self.id_operand(column, 0).unwrap_or(column),
+                self.literal_operand(column, 1).unwrap_or(1),
+            ),
+            _ => (column, 1),
+        };
+
+        let width = self.literal_operand(scalar, 0).unwrap_or(32);
+        let kind = match self.opcode(scalar) {
+            Some(Op::TypeFloat) => ComponentKind::Float,
+            Some(Op::TypeInt) if self.literal_operand(scalar, 1) == Some(1) => ComponentKind::Sint,
+            Some(Op::TypeInt) => ComponentKind::Uint,
+            other => return Err(format!("unsupported vertex input type {other:?}")),
+        };
+
+        let format = vertex_format(kind, width, count)
+            .ok_or_else(|| format!("unsupported vertex input of {count} {width}-bit {kind:?}"))?;
+        Ok(vec![format; columns as usize])
+    }
+}
+
+fn find_decoration<'a>(
+    decorations: Option<&Vec<&'a [Operand]>>,
+    decoration: Decoration,
+) -> Option<&'a [Operand]> {
+    decorations?
+        .iter()
+        .find(
+            |operands| matches!(operands.first(), Some(Operand::Decoration(d)) if *d == decoration),
+        )
+        .copied()
+}
+
+fn literal(operands: &[Operand]) -> Option<u32> {
+    match operands.get(1)? {
+        Operand::LiteralInt32(value) => Some(*value),
+        _ => None,
+    }
+}
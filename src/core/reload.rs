@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use filetime::FileTime;
+
+use super::error::Result;
+use super::shader_compiler;
+
+/// How often the shader sources are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the sources of a shader pair, and every file they `#include`, and recompiles them
+/// in-process with [`shader_compiler`], writing the same `<name>_v.spv`/`<name>_f.spv` files
+/// as the build script.
+pub struct ShaderWatcher {
+    shader: String,
+    includes: Vec<PathBuf>,
+    last_modified: Option<FileTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    /// `shader` is the path of the pair without extension, like `assets/shaders/default`.
+    pub fn new(shader: &str) -> Self {
+        let mut watcher = ShaderWatcher {
+            shader: shader.to_owned(),
+            includes: Vec::new(),
+            last_modified: None,
+            last_poll: Instant::now(),
+        };
+        // The build script already wrote the SPIR-V; compiling again only finds the headers.
+        watcher.includes = watcher
+            .sources()
+            .iter()
+            .filter(|source| source.exists())
+            .filter_map(|source| shader_compiler::compile_file(source).ok())
+            .flat_map(|compiled| compiled.includes)
+            .collect();
+        watcher.last_modified = watcher.newest_modification();
+        watcher
+    }
+
+    /// Returns `true` once for every change to one of the sources. Checks the file system at
+    /// most every [`POLL_INTERVAL`].
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = self.newest_modification();
+        if modified > self.last_modified {
+            self.last_modified = modified;
+            return true;
+        }
+        false
+    }
+
+    /// Compiles both sources. Nothing is written unless every source compiles, so the stages on
+    /// disk always come from the same edit.
+    pub fn compile(&mut self) -> Result<()> {
+        let compiled = self
+            .sources()
+            .into_iter()
+            .filter(|source| source.exists())
+            .map(|source| {
+                let compiled = shader_compiler::compile_file(&source)?;
+                Ok((source, compiled))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut includes = Vec::new();
+        for (source, compiled) in compiled {
+            shader_compiler::write_spv(&source, &compiled)?;
+            includes.extend(compiled.includes);
+        }
+
+        // Headers are only known after a compilation, so start watching any new ones now.
+        self.includes = includes;
+        self.last_modified = self.last_modified.max(self.newest_modification());
+        Ok(())
+    }
+
+    /// GLSL sources, or their HLSL counterparts like `default.vert.hlsl`.
+    fn sources(&self) -> [PathBuf; 4] {
+        [
+            PathBuf::from(format!("{}.vert", self.shader)),
+            PathBuf::from(format!("{}.frag", self.shader)),
+            PathBuf::from(format!("{}.vert.hlsl", self.shader)),
+            PathBuf::from(format!("{}.frag.hlsl", self.shader)),
+        ]
+    }
+
+    fn newest_modification(&self) -> Option<FileTime> {
+        self.sources()
+            .iter()
+            .chain(&self.includes)
+            .filter_map(|source| std::fs::metadata(source).ok())
+            .map(|metadata| FileTime::from_last_modification_time(&metadata))
+            .max()
+    }
+}
@@ -0,0 +1,239 @@
+//! GLSL and HLSL to SPIR-V compilation on top of glslang. The build script includes this file
+//! with `#[path]`, so it may only depend on `std` and `glslang`.
+
+use std::path::{Path, PathBuf};
+
+use glslang::error::GlslangError;
+use glslang::include::{IncludeHandler, IncludeResult, IncludeType};
+use glslang::{Compiler, CompilerOptions, ShaderInput, ShaderSource, ShaderStage, SourceLanguage};
+
+/// Lets GLSL sources use `#include` without declaring the extension themselves, like glslc.
+const INCLUDE_EXTENSION: &str = "#extension GL_GOOGLE_include_directive : require";
+
+/// Includes nested deeper than this are assumed to be recursive.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// A failed compilation, located at the first error glslang reported.
+#[derive(Debug)]
+pub struct CompileError {
+    /// The file the error is in, which is a header when the error comes from an `#include`.
+    pub path: PathBuf,
+    /// 1-based line of the error, when glslang reported one.
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// SPIR-V for one source, with every file it pulled in through `#include`.
+pub struct CompiledShader {
+    pub code: Vec<u32>,
+    pub includes: Vec<PathBuf>,
+}
+
+/// The stage and language of a shader source, taken from its extension. GLSL sources end in
+/// `.vert`, `.frag`, `.geom` or `.comp`; HLSL sources add `.hlsl`, like `sky.frag.hlsl`, and
+/// use `main` as entry point.
+pub fn shader_kind(path: &Path) -> Option<(ShaderStage, SourceLanguage)> {
+    let (extension, language) = match path.extension()?.to_str()? {
+        "hlsl" => (
+            Path::new(path.file_stem()?).extension()?.to_str()?,
+            SourceLanguage::HLSL,
+        ),
+        extension => (extension, SourceLanguage::GLSL),
+    };
+
+    let stage = match extension {
+        "vert" => ShaderStage::Vertex,
+        "frag" => ShaderStage::Fragment,
+        "geom" => ShaderStage::Geometry,
+        "comp" => ShaderStage::Compute,
+        _ => return None,
+    };
+    Some((stage, language))
+}
+
+/// Where the SPIR-V for `source` goes: `shaders/default.vert` becomes `shaders/default_v.spv`.
+pub fn spv_path(source: &Path) -> Option<PathBuf> {
+    let file_name = source.file_name()?.to_str()?;
+    let (name, extensions) = file_name.split_once('.')?;
+    let stage = extensions.chars().next()?;
+    Some(source.with_file_name(format!("{name}_{stage}.spv")))
+}
+
+/// Compiles the source at `path`. `#include "file"` resolves relative to the including file,
+/// `#include <file>` relative to the directory of `path`.
+pub fn compile_file(path: &Path) -> Result<CompiledShader, CompileError> {
+    let error = |message: String| CompileError {
+        path: path.to_owned(),
+        line: None,
+        message,
+    };
+
+    let (stage, language) =
+        shader_kind(path).ok_or_else(|| error("unknown shader stage".to_owned()))?;
+    let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let compiler =
+        Compiler::acquire().ok_or_else(|| error("failed to initialize glslang".to_owned()))?;
+
+    let source = ShaderSource::from(match language {
+        SourceLanguage::GLSL => enable_includes(&text),
+        _ => text,
+    });
+    let options = CompilerOptions {
+        source_language: language,
+        ..CompilerOptions::default()
+    };
+    let mut includer = Includer {
+        root: path.parent().unwrap_or(Path::new("")).to_owned(),
+        included: Vec::new(),
+    };
+
+    let code = {
+        let input = ShaderInput::new(
+            &source,
+            stage,
+            &options,
+            None::<&[(&str, Option<&str>)]>,
+            Some(&mut includer),
+        )
+        .map_err(|e| error(e.to_string()))?;
+
+        glslang::Shader::new(compiler, input)
+            .parse()
+            .and_then(|shader| shader.compile())
+            .map_err(|e| located_error(path, e))?
+    };
+
+    Ok(CompiledShader {
+        code,
+        includes: includer.included,
+    })
+}
+
+/// Compiles `source` and writes the result to [`spv_path`]. Nothing is written when the
+/// compilation fails, so an earlier SPIR-V file stays in place.
+pub fn compile_to_spv(source: &Path) -> Result<CompiledShader, CompileError> {
+    let compiled = compile_file(source)?;
+    write_spv(source, &compiled)?;
+    Ok(compiled)
+}
+
+/// Writes `compiled`, the SPIR-V of `source`, to [`spv_path`].
+pub fn write_spv(source: &Path, compiled: &CompiledShader) -> Result<(), CompileError> {
+    let output = spv_path(source).ok_or_else(|| CompileError {
+        path: source.to_owned(),
+        line: None,
+        message: "unknown shader stage".to_owned(),
+    })?;
+
+    let bytes: Vec<u8> = compiled
+        .code
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    std::fs::write(&output, bytes).map_err(|e| CompileError {
+        path: output,
+        line: None,
+        message: e.to_string(),
+    })
+}
+
+/// Enables `#include` right after the `#version` directive, which has to come first, and
+/// resets the line counter so errors still point at the right line.
+fn enable_includes(text: &str) -> String {
+    let Some(version) = text
+        .lines()
+        .position(|line| line.trim_start().starts_with("#version"))
+    else {
+        return text.to_owned();
+    };
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    let line = format!("#line {}", version + 2);
+    lines.splice(version + 1..version + 1, [INCLUDE_EXTENSION, &line]);
+    lines.join("\n")
+}
+
+/// Turns glslang's log into an error at the first `ERROR: <file>:<line>: <message>` entry.
+/// The main source is reported as file `0`, headers by the name the includer gave them.
+fn located_error(path: &Path, error: GlslangError) -> CompileError {
+    let log = match &error {
+        GlslangError::PreprocessError(log)
+        | GlslangError::ParseError(log)
+        | GlslangError::MapIoError(log)
+        | GlslangError::LinkError(log) => log.log.clone(),
+        other => other.to_string(),
+    };
+
+    let located = log.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("ERROR: ")?;
+        let (location, message) = rest.split_once(": ")?;
+        let (file, line) = location.rsplit_once(':')?;
+        let line = line.parse().ok()?;
+        let file = match file {
+            "" | "0" => path.to_owned(),
+            header => PathBuf::from(header),
+        };
+        Some((file, line, message.trim().to_owned()))
+    });
+
+    match located {
+        Some((path, line, message)) => CompileError {
+            path,
+            line: Some(line),
+            message,
+        },
+        None => CompileError {
+            path: path.to_owned(),
+            line: None,
+            message: log.trim().to_owned(),
+        },
+    }
+}
+
+struct Includer {
+    root: PathBuf,
+    included: Vec<PathBuf>,
+}
+
+impl IncludeHandler for Includer {
+    fn include(
+        &mut self,
+        ty: IncludeType,
+        header_name: &str,
+        includer_name: &str,
+        include_depth: usize,
+    ) -> Option<IncludeResult> {
+        if include_depth > MAX_INCLUDE_DEPTH {
+            return None;
+        }
+
+        let directory = match ty {
+            IncludeType::Local if !includer_name.is_empty() => Path::new(includer_name)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_owned(),
+            _ => self.root.clone(),
+        };
+        let path = directory.join(header_name);
+        let data = std::fs::read_to_string(&path).ok()?;
+
+        if !self.included.contains(&path) {
+            self.included.push(path.clone());
+        }
+        Some(IncludeResult {
+            name: path.to_string_lossy().into_owned(),
+            data,
+        })
+    }
+}
@@ -1,6 +1,8 @@
 use ash::vk;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+use super::error::Result;
+
 #[derive(Clone)]
 pub struct SurfaceInfo {
     pub surface: vk::SurfaceKHR,
@@ -11,7 +13,7 @@ pub fn create_surface(
     window: &winit::window::Window,
     entry: &ash::Entry,
     instance: &ash::Instance,
-) -> SurfaceInfo {
+) -> Result<SurfaceInfo> {
     let surface = unsafe {
         ash_window::create_surface(
             entry,
@@ -20,13 +22,12 @@ pub fn create_surface(
             window.raw_window_handle(),
             None,
         )
-        .expect("Failed to create surface")
-    };
+    }?;
 
     let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 
-    SurfaceInfo {
+    Ok(SurfaceInfo {
         surface,
         surface_loader,
-    }
+    })
 }
@@ -1,136 +1,483 @@
 use ash::vk::{self, CompositeAlphaFlagsKHR};
+use gpu_allocator::vulkan;
 
-use super::{device::DeviceInfo, surface::SurfaceInfo};
+use super::{
+    device::DeviceInfo,
+    error::Result,
+    framebuffer::create_framebuffers,
+    images::{create_color_target, create_depth_image, destroy_image, Image},
+    surface::SurfaceInfo,
+};
 
-#[derive(Clone)]
-pub struct SwapchainInfo {
+/// How finished frames are handed to the display. Modes the surface doesn't support fall back
+/// to the next best one, ending at vsync, which every surface supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vertical blank, capping the frame rate at the refresh rate without tearing.
+    #[default]
+    Vsync,
+    /// Replaces the queued frame with the newest one, so input latency stays low without
+    /// tearing. Falls back to uncapped, then vsync.
+    LowLatency,
+    /// Presents right away, which may tear. Falls back to low latency, then vsync.
+    Uncapped,
+}
+
+impl PresentMode {
+    /// The Vulkan present modes that implement this mode, best first.
+    fn candidates(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentMode::Vsync => &[vk::PresentModeKHR::FIFO],
+            PresentMode::LowLatency => &[
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::FIFO,
+            ],
+            PresentMode::Uncapped => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+}
+
+/// The first present mode of `mode`'s fallback chain in `supported`. FIFO is guaranteed to be
+/// available, so it is the last resort.
+pub fn choose_present_mode(
+    mode: PresentMode,
+    supported: &[vk::PresentModeKHR],
+) -> vk::PresentModeKHR {
+    mode.candidates()
+        .iter()
+        .copied()
+        .find(|candidate| supported.contains(candidate))
+        .unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+/// The number of swapchain images to ask for: `requested`, or one more than the minimum so the
+/// application doesn't wait on the driver to release an image, clamped to what the surface
+/// allows. A `max_count` of 0 means there is no maximum.
+pub fn choose_image_count(requested: Option<u32>, min_count: u32, max_count: u32) -> u32 {
+    let count = requested.unwrap_or(min_count + 1).max(min_count);
+    if max_count == 0 {
+        count
+    } else {
+        count.min(max_count)
+    }
+}
+
+/// Specialization constant the chosen [`ColorOutput`] is passed to fragment shaders in, as
+/// `layout(constant_id = 100) const uint OUTPUT_COLOR_SPACE`. Shaders without it ignore it.
+pub const COLOR_OUTPUT_CONSTANT_ID: u32 = 100;
+
+/// Color spaces the swapchain can present in. The game lists the ones it can render for, best
+/// first, and the first one the surface supports is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorOutput {
+    /// 8-bit sRGB. The hardware encodes the shader's linear output.
+    #[default]
+    Srgb,
+    /// 10-bit BT.2020 primaries with the ST 2084 (PQ) transfer function. The shader writes
+    /// PQ-encoded values.
+    Hdr10,
+    /// 16-bit float extended sRGB, linear, where 1.0 is 80 nits. The shader writes linear values.
+    ScRgb,
+}
+
+impl ColorOutput {
+    /// The surface formats that implement this output, best first.
+    fn surface_formats(self) -> Vec<vk::SurfaceFormatKHR> {
+        let format = |format, color_space| vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        };
+
+        match self {
+            ColorOutput::Srgb => vec![
+                format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                format(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            ColorOutput::Hdr10 => vec![
+                format(
+                    vk::Format::A2B10G10R10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+                format(
+                    vk::Format::A2R10G10B10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+            ],
+            ColorOutput::ScRgb => vec![format(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            )],
+        }
+    }
+
+    /// Value of [`COLOR_OUTPUT_CONSTANT_ID`]: 0 for sRGB, 1 for HDR10 and 2 for scRGB.
+    pub fn shader_value(self) -> u32 {
+        match self {
+            ColorOutput::Srgb => 0,
+            ColorOutput::Hdr10 => 1,
+            ColorOutput::ScRgb => 2,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool {
+        self != ColorOutput::Srgb
+    }
+}
+
+/// The surface format for the first output in `preferences` that `supported` has. Without a
+/// match, falls back to sRGB and then to the first supported format, which is treated as sRGB.
+pub fn choose_surface_format(
+    preferences: &[ColorOutput],
+    supported: &[vk::SurfaceFormatKHR],
+) -> (vk::SurfaceFormatKHR, ColorOutput) {
+    preferences
+        .iter()
+        .chain(&[ColorOutput::Srgb])
+        .find_map(|output| {
+            output
+                .surface_formats()
+                .into_iter()
+                .find(|format| supported.contains(format))
+                .map(|format| (format, *output))
+        })
+        .unwrap_or((supported[0], ColorOutput::Srgb))
+}
+
+/// Mastering display and content light levels sent to HDR displays through
+/// `VK_EXT_hdr_metadata`, so they can tone map what they can't show. Luminances are in nits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdrMetadata {
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    pub max_content_light_level: f32,
+    pub max_frame_average_light_level: f32,
+}
+
+impl Default for HdrMetadata {
+    fn default() -> Self {
+        HdrMetadata {
+            max_luminance: 1000.0,
+            min_luminance: 0.001,
+            max_content_light_level: 1000.0,
+            max_frame_average_light_level: 400.0,
+        }
+    }
+}
+
+/// Sends `metadata` for `swapchain`, mastered with BT.2020 primaries and a D65 white point.
+pub(crate) fn set_hdr_metadata(
+    device: &ash::Device,
+    hdr_metadata_fn: &vk::ExtHdrMetadataFn,
+    swapchain: vk::SwapchainKHR,
+    metadata: &HdrMetadata,
+) {
+    let xy = |x, y| vk::XYColorEXT { x, y };
+    let metadata = vk::HdrMetadataEXT::builder()
+        .display_primary_red(xy(0.708, 0.292))
+        .display_primary_green(xy(0.170, 0.797))
+        .display_primary_blue(xy(0.131, 0.046))
+        .white_point(xy(0.3127, 0.3290))
+        .max_luminance(metadata.max_luminance)
+        .min_luminance(metadata.min_luminance)
+        .max_content_light_level(metadata.max_content_light_level)
+        .max_frame_average_light_level(metadata.max_frame_average_light_level);
+
+    unsafe { (hdr_metadata_fn.set_hdr_metadata_ext)(device.handle(), 1, &swapchain, &*metadata) };
+}
+
+/// The extent to create the swapchain with. Most surfaces dictate it through
+/// `current_extent`; the others report `u32::MAX` there and take the window size, clamped to
+/// what they support.
+pub fn choose_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    window_size: vk::Extent2D,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        return capabilities.current_extent;
+    }
+
+    vk::Extent2D {
+        width: window_size.width.clamp(
+            capabilities.min_image_extent.width,
+            capabilities.max_image_extent.width,
+        ),
+        height: window_size.height.clamp(
+            capabilities.min_image_extent.height,
+            capabilities.max_image_extent.height,
+        ),
+    }
+}
+
+/// A swapchain with everything sized after it: its image views, the depth and multisampled
+/// color attachments and one framebuffer per image. It is replaced as a whole when the surface
+/// changes, and the old one is destroyed once the frames that used it are done.
+pub(crate) struct Swapchain {
     pub loader: ash::extensions::khr::Swapchain,
-    pub swapchains: Vec<vk::SwapchainKHR>,
-    pub swapchain_views: Vec<vk::ImageView>,
-    pub formats: Vec<vk::SurfaceFormatKHR>,
+    pub swapchain: vk::SwapchainKHR,
+    pub views: Vec<vk::ImageView>,
+    pub depth_image: Option<Image>,
+    pub color_image: Option<Image>,
+    pub framebuffers: Vec<vk::Framebuffer>,
     pub extent: vk::Extent2D,
-    pub current_format: vk::Format,
+    pub format: vk::Format,
+    /// The color space `format` is presented in.
+    pub output: ColorOutput,
+    pub present_mode: vk::PresentModeKHR,
+    /// The graphics timeline value of the frame that last rendered to each image, 0 if none
+    /// has. An image can come back from acquire while that frame is still in flight.
+    pub image_values: Vec<u64>,
+    /// Signaled when rendering to each image is done, for presentation to wait on. There is one
+    /// per image rather than per frame in flight, since presentation may still wait on an
+    /// earlier image's semaphore when the next frame is submitted.
+    pub render_finished: Vec<vk::Semaphore>,
 }
 
-pub fn create_swapchain(
-    device_info: DeviceInfo,
-    surface_info: SurfaceInfo,
-    instance: &ash::Instance,
-    window: &winit::window::Window,
-    swapchains: Option<Vec<vk::SwapchainKHR>>,
-) -> SwapchainInfo {
-    unsafe { device_info.device.device_wait_idle() }.expect("Failed to wait for device idle");
-
-    let capabilities = unsafe {
-        surface_info
-            .surface_loader
-            .get_physical_device_surface_capabilities(
-                device_info.logical_devices[0].physical_device,
-                surface_info.surface,
+impl Swapchain {
+    /// Creates a swapchain for `surface_info` in the first of `color_outputs` the surface
+    /// supports, handing `old_swapchain` over to it if there is one. Returns `None` while the
+    /// surface has no area, such as when the window is minimized. The attachments and
+    /// framebuffers are added by [`Swapchain::attach`].
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create(
+        device_info: &DeviceInfo,
+        surface_info: &SurfaceInfo,
+        instance: &ash::Instance,
+        window_size: vk::Extent2D,
+        color_outputs: &[ColorOutput],
+        present_mode: PresentMode,
+        image_count: Option<u32>,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<Option<Swapchain>> {
+        let physical_device = device_info.logical_devices[0].physical_device;
+
+        let capabilities = unsafe {
+            surface_info
+                .surface_loader
+                .get_physical_device_surface_capabilities(physical_device, surface_info.surface)
+        }?;
+
+        let extent = choose_extent(&capabilities, window_size);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(None);
+        }
+
+        let (surface_format, output) =
+            Swapchain::choose_format(device_info, surface_info, color_outputs)?;
+
+        let present_modes = unsafe {
+            surface_info
+                .surface_loader
+                .get_physical_device_surface_present_modes(physical_device, surface_info.surface)
+        }?;
+        let present_mode = choose_present_mode(present_mode, &present_modes);
+        let image_count = choose_image_count(
+            image_count,
+            capabilities.min_image_count,
+            capabilities.max_image_count,
+        );
+
+        // Images are rendered on the graphics queue and handed to the present queue.
+        let (sharing_mode, indices) = if device_info.graphics_family == device_info.present_family {
+            (vk::SharingMode::EXCLUSIVE, vec![])
+        } else {
+            (
+                vk::SharingMode::CONCURRENT,
+                vec![device_info.graphics_family, device_info.present_family],
             )
+        };
+
+        let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
+            .surface(surface_info.surface)
+            .pre_transform(capabilities.current_transform)
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_format(surface_format.format)
+            .image_color_space(surface_format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_sharing_mode(sharing_mode)
+            .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
+            .min_image_count(image_count)
+            .clipped(true)
+            .queue_family_indices(&indices)
+            .old_swapchain(old_swapchain)
+            .present_mode(present_mode);
+
+        let loader = ash::extensions::khr::Swapchain::new(instance, &device_info.device);
+
+        let swapchain = unsafe { loader.create_swapchain(&swapchain_create_info, None) }?;
+
+        let mut swapchain = Swapchain {
+            loader,
+            swapchain,
+            views: Vec::new(),
+            depth_image: None,
+            color_image: None,
+            framebuffers: Vec::new(),
+            extent,
+            format: surface_format.format,
+            output,
+            present_mode,
+            image_values: Vec::new(),
+            render_finished: Vec::new(),
+        };
+
+        if let Err(e) = swapchain.create_views(&device_info.device) {
+            swapchain.destroy_views(&device_info.device);
+            return Err(e);
+        }
+
+        Ok(Some(swapchain))
     }
-    .expect("Failed to get capabilities");
 
-    let formats = unsafe {
-        surface_info
-            .surface_loader
-            .get_physical_device_surface_formats(
-                device_info.logical_devices[0].physical_device,
-                surface_info.surface,
-            )
+    /// Creates a view and a render-finished semaphore for each swapchain image.
+    fn create_views(&mut self, device: &ash::Device) -> Result<()> {
+        let images = unsafe { self.loader.get_swapchain_images(self.swapchain) }?;
+        self.image_values = vec![0; images.len()];
+
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+
+        for image in images {
+            self.render_finished
+                .push(unsafe { device.create_semaphore(&semaphore_info, None) }?);
+
+            let subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .level_count(1)
+                .base_mip_level(0)
+                .layer_count(1)
+                .base_array_layer(0);
+
+            let view_create_info = vk::ImageViewCreateInfo::builder()
+                .format(self.format)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .subresource_range(*subresource_range)
+                .image(image);
+
+            let view = unsafe { device.create_image_view(&view_create_info, None) }?;
+
+            self.views.push(view);
+        }
+
+        Ok(())
     }
-    .expect("Failed to get device formats");
 
-    let indices: Vec<u32> = device_info
-        .queue_families
-        .into_iter()
-        .map(|k| k.index)
-        .collect();
+    /// The surface format and color space [`Swapchain::create`] picks for `color_outputs`.
+    pub(crate) fn choose_format(
+        device_info: &DeviceInfo,
+        surface_info: &SurfaceInfo,
+        color_outputs: &[ColorOutput],
+    ) -> Result<(vk::SurfaceFormatKHR, ColorOutput)> {
+        let formats = unsafe {
+            surface_info
+                .surface_loader
+                .get_physical_device_surface_formats(
+                    device_info.logical_devices[0].physical_device,
+                    surface_info.surface,
+                )
+        }?;
 
-    let mut new_swapchains = swapchains.clone().unwrap_or_else(|| vec![vk::SwapchainKHR::null()]);
-    let last_swapchain = *swapchains
-        .unwrap_or_else(|| new_swapchains.clone())
-        .last()
-        .unwrap_or(&vk::SwapchainKHR::null());
+        Ok(choose_surface_format(color_outputs, &formats))
+    }
 
-    let size = window.inner_size();
-    let extent = vk::Extent2D::builder()
-        .width(size.width)
-        .height(size.height);
+    /// Creates the depth and multisampled color attachments and the framebuffers for
+    /// `render_pass`.
+    pub(crate) fn attach(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        samples: vk::SampleCountFlags,
+        render_pass: vk::RenderPass,
+    ) -> Result<()> {
+        let result = self
+            .create_attachments(device, allocator, samples)
+            .and_then(|()| self.create_framebuffers(device, render_pass));
 
-    let format = if formats
-        .iter()
-        .filter(|format| format.format == vk::Format::B8G8R8A8_SRGB)
-        .map(|format| format.format)
-        .next()
-        .is_some()
-    {
-        vk::Format::B8G8R8A8_SRGB
-    } else {
-        formats[0].format
-    };
-
-    let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
-        .surface(surface_info.surface)
-        .pre_transform(capabilities.current_transform)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-        .image_format(format)
-        .image_color_space(formats[0].color_space)
-        .image_extent(*extent)
-        .image_array_layers(1)
-        .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-        .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
-        .min_image_count(capabilities.min_image_count)
-        .clipped(true)
-        .queue_family_indices(&indices)
-        .old_swapchain(last_swapchain)
-        .present_mode(vk::PresentModeKHR::IMMEDIATE);
-
-    let loader = ash::extensions::khr::Swapchain::new(instance, &device_info.device);
-
-    let swapchain = unsafe {
-        loader
-            .create_swapchain(&swapchain_create_info, None)
-            .expect("Failed to create swapchain")
-    };
-
-    let swapchain_images =
-        unsafe { loader.get_swapchain_images(swapchain) }.expect("Failed to get swapchain images");
-
-    let mut swapchain_views: Vec<vk::ImageView> = Vec::new();
-
-    for image in swapchain_images {
-        let subresource_range = vk::ImageSubresourceRange::builder()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .level_count(1)
-            .base_mip_level(0)
-            .layer_count(1)
-            .base_array_layer(0);
-
-        let view_create_info = vk::ImageViewCreateInfo::builder()
-            .format(format)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .subresource_range(*subresource_range)
-            .image(image);
-
-        let view = unsafe {
-            device_info
-                .device
-                .create_image_view(&view_create_info, None)
+        match result {
+            Ok(framebuffers) => {
+                self.framebuffers = framebuffers;
+                Ok(())
+            }
+            Err(e) => {
+                self.destroy_attachments(device, allocator);
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates the depth and multisampled color attachments, without framebuffers.
+    pub(crate) fn create_attachments(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        self.depth_image = Some(create_depth_image(device, allocator, self.extent, samples)?);
+        self.color_image =
+            create_color_target(device, allocator, self.extent, self.format, samples)?;
+        Ok(())
+    }
+
+    /// Framebuffers of the swapchain images for `render_pass`, which has to be compatible with
+    /// the attachments.
+    pub(crate) fn create_framebuffers(
+        &self,
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+    ) -> Result<Vec<vk::Framebuffer>> {
+        create_framebuffers(
+            &self.views,
+            self.depth_image
+                .as_ref()
+                .expect("Failed to get depth image")
+                .view,
+            self.color_image.as_ref().map(|image| image.view),
+            self.extent,
+            render_pass,
+            device,
+        )
+    }
+
+    /// Destroys the swapchain and everything sized after it. None of it may still be in use.
+    pub(crate) fn destroy(&mut self, device: &ash::Device, allocator: &mut vulkan::Allocator) {
+        self.destroy_attachments(device, allocator);
+        self.destroy_views(device);
+    }
+
+    /// Destroys the framebuffers and the depth and multisampled color attachments.
+    fn destroy_attachments(&mut self, device: &ash::Device, allocator: &mut vulkan::Allocator) {
+        for framebuffer in self.framebuffers.drain(..) {
+            unsafe { device.destroy_framebuffer(framebuffer, None) }
+        }
+
+        if let Some(depth_image) = self.depth_image.take() {
+            if let Err(e) = destroy_image(device, allocator, depth_image) {
+                error!("Failed to free depth image: {e}");
+            }
         }
-        .expect("Failed to create image view");
 
-        swapchain_views.push(view);
+        if let Some(color_image) = self.color_image.take() {
+            if let Err(e) = destroy_image(device, allocator, color_image) {
+                error!("Failed to free color image: {e}");
+            }
+        }
     }
 
-    new_swapchains.push(swapchain);
+    /// Destroys the image views, the semaphores and the swapchain itself.
+    fn destroy_views(&mut self, device: &ash::Device) {
+        for view in self.views.drain(..) {
+            unsafe { device.destroy_image_view(view, None) }
+        }
+
+        for semaphore in self.render_finished.drain(..) {
+            unsafe { device.destroy_semaphore(semaphore, None) }
+        }
 
-    SwapchainInfo {
-        swapchains: new_swapchains.to_vec(),
-        loader,
-        swapchain_views,
-        extent: *extent,
-        formats,
-        current_format: format
+        unsafe { self.loader.destroy_swapchain(self.swapchain, None) };
+        self.swapchain = vk::SwapchainKHR::null();
     }
 }
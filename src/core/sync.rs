@@ -1,33 +1,56 @@
 use ash::vk;
+use gpu_allocator::vulkan;
 
-use super::app::MAX_CONCURRENT_FRAMES;
+use super::buffers::{destroy_buffer, Buffer};
+use super::error::Result;
 
-#[derive(Clone)]
-pub struct SyncInfo {
-    pub render_semaphores: Vec<vk::Semaphore>,
-    pub image_semaphores: Vec<vk::Semaphore>,
-    pub frame_fences: Vec<vk::Fence>,
+/// Everything one frame in flight records into and synchronizes with. A frame's resources are
+/// only touched again once the graphics timeline reaches `submitted`.
+pub(crate) struct FrameContext {
+    pub command_buffer: vk::CommandBuffer,
+    /// Signaled when the acquired swapchain image can be rendered to.
+    pub image_available: vk::Semaphore,
+    /// The graphics timeline value the frame's last submission signals, 0 before the first.
+    pub submitted: u64,
+    /// Holds the camera of this frame.
+    pub uniform_buffer: Buffer,
+    pub descriptor_set: vk::DescriptorSet,
 }
 
-pub fn create_sync(device: &ash::Device) -> SyncInfo {
+/// Creates one frame context for each of `command_buffers`, which must come with as many
+/// uniform buffers and descriptor sets.
+pub(crate) fn create_frames(
+    device: &ash::Device,
+    command_buffers: Vec<vk::CommandBuffer>,
+    uniform_buffers: Vec<Buffer>,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+) -> Result<Vec<FrameContext>> {
     let semaphore_info = vk::SemaphoreCreateInfo::builder();
-    let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-    let mut render_semaphores = vec![vk::Semaphore::null(); MAX_CONCURRENT_FRAMES.into()];
-    let mut image_semaphores = vec![vk::Semaphore::null(); MAX_CONCURRENT_FRAMES.into()];
-    let mut frame_fences = vec![vk::Fence::null(); MAX_CONCURRENT_FRAMES.into()];
 
-    for i in 0..MAX_CONCURRENT_FRAMES {
-        render_semaphores[i as usize] = unsafe { device.create_semaphore(&semaphore_info, None) }
-            .expect("Failed to create semaphore");
-        image_semaphores[i as usize] = unsafe { device.create_semaphore(&semaphore_info, None) }
-            .expect("Failed to create semaphore");
-        frame_fences[i as usize] =
-            unsafe { device.create_fence(&fence_info, None) }.expect("Failed to create fence");
-    }
+    command_buffers
+        .into_iter()
+        .zip(uniform_buffers)
+        .zip(descriptor_sets)
+        .map(|((command_buffer, uniform_buffer), descriptor_set)| {
+            Ok(FrameContext {
+                command_buffer,
+                image_available: unsafe { device.create_semaphore(&semaphore_info, None) }?,
+                submitted: 0,
+                uniform_buffer,
+                descriptor_set,
+            })
+        })
+        .collect()
+}
+
+impl FrameContext {
+    /// Frees the frame's semaphore and uniform buffer. The command buffer and descriptor set
+    /// go with their pools.
+    pub(crate) fn destroy(self, device: &ash::Device, allocator: &mut vulkan::Allocator) {
+        unsafe { device.destroy_semaphore(self.image_available, None) };
 
-    SyncInfo {
-        render_semaphores,
-        image_semaphores,
-        frame_fences,
+        if let Err(e) = destroy_buffer(device, allocator, self.uniform_buffer) {
+            error!("Failed to free uniform buffer: {e}");
+        }
     }
 }
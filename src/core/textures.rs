@@ -0,0 +1,121 @@
+use ash::vk;
+
+use gpu_allocator::vulkan;
+
+use super::error::{Result, VkcrError};
+use super::images::{create_image, destroy_image, Image};
+use super::upload::{UploadHandle, UploadManager};
+
+pub const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+pub struct Texture {
+    pub image: Image,
+    pub sampler: vk::Sampler,
+}
+
+/// Loads a PNG or JPEG file into a device-local, shader-readable texture.
+pub fn create_texture_from_file(
+    path: &str,
+    allocator: &mut vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+) -> Result<Texture> {
+    let pixels = image::open(path)
+        .map_err(|e| VkcrError::Texture {
+            path: path.to_owned(),
+            message: e.to_string(),
+        })?
+        .to_rgba8();
+
+    create_texture(&pixels, path, allocator, device, uploads)
+}
+
+/// A 1x1 white texture, bound when no texture is configured so untextured geometry keeps its
+/// vertex colors.
+pub fn create_default_texture(
+    allocator: &mut vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+) -> Result<Texture> {
+    let pixels = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+
+    create_texture(&pixels, "Default", allocator, device, uploads)
+}
+
+/// Creates a texture and queues the upload of `pixels` to it, without waiting for the copy.
+/// Frames wait for pending uploads before they sample it.
+pub fn create_texture(
+    pixels: &image::RgbaImage,
+    name: &str,
+    allocator: &mut vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+) -> Result<Texture> {
+    let (texture, _) = upload_texture(pixels, name, allocator, device, uploads)?;
+
+    Ok(texture)
+}
+
+/// Like [`create_texture`], but also returns the handle `uploads` reports resident once the
+/// copy is done.
+pub fn upload_texture(
+    pixels: &image::RgbaImage,
+    name: &str,
+    allocator: &mut vulkan::Allocator,
+    device: &ash::Device,
+    uploads: &mut UploadManager,
+) -> Result<(Texture, UploadHandle)> {
+    // Zero-sized images are invalid, and there would be nothing to copy.
+    if pixels.is_empty() {
+        return Err(VkcrError::EmptyUpload {
+            name: name.to_owned(),
+        });
+    }
+
+    let extent = vk::Extent2D {
+        width: pixels.width(),
+        height: pixels.height(),
+    };
+
+    let image = create_image(
+        device,
+        allocator,
+        extent,
+        TEXTURE_FORMAT,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        vk::ImageAspectFlags::COLOR,
+        &uploads.transfer().queue_families,
+        name,
+    )?;
+
+    let upload = uploads.upload_image(device, allocator, pixels.as_raw(), image.image, extent)?;
+
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .min_lod(0.0)
+        .max_lod(0.0);
+
+    let sampler = unsafe { device.create_sampler(&sampler_info, None) }?;
+
+    Ok((Texture { image, sampler }, upload))
+}
+
+pub fn destroy_texture(
+    device: &ash::Device,
+    allocator: &mut vulkan::Allocator,
+    texture: Texture,
+) -> Result<()> {
+    unsafe { device.destroy_sampler(texture.sampler, None) };
+
+    destroy_image(device, allocator, texture.image)
+}
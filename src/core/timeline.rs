@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use super::error::Result;
+
+/// Bookkeeping of a counter that every submission to one queue advances by one. The GPU
+/// finishes a queue's submissions in order, so a single value tells everything that is done.
+#[derive(Clone, Debug, Default)]
+pub struct TimelineCounter {
+    submitted: u64,
+    completed: u64,
+}
+
+impl TimelineCounter {
+    pub fn new() -> Self {
+        TimelineCounter::default()
+    }
+
+    /// The value the next submission signals.
+    pub fn next(&self) -> u64 {
+        self.submitted + 1
+    }
+
+    /// Records a submission and returns the value it signals when done.
+    pub fn submit(&mut self) -> u64 {
+        self.submitted += 1;
+        self.submitted
+    }
+
+    /// The value of the latest submission, or 0 before the first.
+    pub fn submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    /// The highest value seen completed.
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    /// Records that the GPU reached `value`. Lower values than seen before are ignored, since
+    /// the counter only goes up.
+    pub fn observe(&mut self, value: u64) {
+        self.completed = self.completed.max(value.min(self.submitted));
+    }
+
+    /// Whether the submission that signals `value` is known to be done. Value 0 stands for
+    /// nothing submitted and is always complete.
+    pub fn is_complete(&self, value: u64) -> bool {
+        value <= self.completed
+    }
+}
+
+/// What tells the CPU how far the GPU got.
+enum Signal {
+    /// A Vulkan 1.2 timeline semaphore that holds the counter itself.
+    Semaphore(vk::Semaphore),
+    /// Devices without timeline semaphores get a fence for each pending submission, oldest
+    /// first. Signaled fences are reset and kept for reuse.
+    Fences {
+        pending: VecDeque<(u64, vk::Fence)>,
+        free: Vec<vk::Fence>,
+    },
+}
+
+/// Tracks the progress of one queue with a monotonically increasing value. Each
+/// [`Timeline::submit`] signals the next value, and the CPU asks whether a value has completed
+/// or waits for it instead of keeping a fence per submission around.
+pub(crate) struct Timeline {
+    counter: TimelineCounter,
+    signal: Signal,
+}
+
+impl Timeline {
+    /// Uses a timeline semaphore if `timeline_semaphores` is set, see
+    /// [`DeviceInfo::timeline_semaphores`](super::device::DeviceInfo::timeline_semaphores).
+    pub(crate) fn new(device: &ash::Device, timeline_semaphores: bool) -> Result<Self> {
+        let signal = if timeline_semaphores {
+            let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+                .semaphore_type(vk::SemaphoreType::TIMELINE)
+                .initial_value(0);
+            let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_info);
+            Signal::Semaphore(unsafe { device.create_semaphore(&create_info, None) }?)
+        } else {
+            Signal::Fences {
+                pending: VecDeque::new(),
+                free: Vec::new(),
+            }
+        };
+
+        Ok(Timeline {
+            counter: TimelineCounter::new(),
+            signal,
+        })
+    }
+
+    /// The value of the latest submission, which is complete once everything submitted so far
+    /// is.
+    pub(crate) fn submitted(&self) -> u64 {
+        self.counter.submitted()
+    }
+
+    /// The timeline semaphore other queues can wait on, if the device has them.
+    pub(crate) fn semaphore(&self) -> Option<vk::Semaphore> {
+        match self.signal {
+            Signal::Semaphore(semaphore) => Some(semaphore),
+            Signal::Fences { .. } => None,
+        }
+    }
+
+    /// Submits `command_buffers` to `queue` and returns the value reached once they are done.
+    /// `wait_semaphores` are waited on at their stage, timeline semaphores until they reach
+    /// their value, which binary ones ignore. `signal_semaphores` are signaled, as with a plain
+    /// submit.
+    pub(crate) fn submit(
+        &mut self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        command_buffers: &[vk::CommandBuffer],
+        wait_semaphores: &[(vk::Semaphore, u64, vk::PipelineStageFlags)],
+        signal_semaphores: &[vk::Semaphore],
+    ) -> Result<u64> {
+        let value = self.counter.next();
+
+        let wait_values: Vec<_> = wait_semaphores.iter().map(|(_, value, _)| *value).collect();
+        let wait_stages: Vec<_> = wait_semaphores.iter().map(|(.., stage)| *stage).collect();
+        let wait_semaphores: Vec<_> = wait_semaphores
+            .iter()
+            .map(|(semaphore, ..)| *semaphore)
+            .collect();
+        let mut signal_semaphores = signal_semaphores.to_vec();
+
+        match &mut self.signal {
+            Signal::Semaphore(semaphore) => {
+                // Values are only read for timeline semaphores, but every semaphore needs one.
+                let mut signal_values = vec![0; signal_semaphores.len()];
+                signal_semaphores.push(*semaphore);
+                signal_values.push(value);
+
+                let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+                    .wait_semaphore_values(&wait_values)
+                    .signal_semaphore_values(&signal_values);
+                let submit_info = vk::SubmitInfo::builder()
+                    .wait_semaphores(&wait_semaphores)
+                    .wait_dst_stage_mask(&wait_stages)
+                    .command_buffers(command_buffers)
+                    .signal_semaphores(&signal_semaphores)
+                    .push_next(&mut timeline_info);
+
+                unsafe { device.queue_submit(queue, &[*submit_info], vk::Fence::null()) }?;
+            }
+            Signal::Fences { pending, free } => {
+                let fence = match free.pop() {
+                    Some(fence) => fence,
+                    None => unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None) }?,
+                };
+
+                let submit_info = vk::SubmitInfo::builder()
+                    .wait_semaphores(&wait_semaphores)
+                    .wait_dst_stage_mask(&wait_stages)
+                    .command_buffers(command_buffers)
+                    .signal_semaphores(&signal_semaphores);
+
+                if let Err(e) = unsafe { device.queue_submit(queue, &[*submit_info], fence) } {
+                    free.push(fence);
+                    return Err(e.into());
+                }
+                pending.push_back((value, fence));
+            }
+        }
+
+        Ok(self.counter.submit())
+    }
+
+    /// The highest value the GPU has completed, without blocking.
+    pub(crate) fn completed(&mut self, device: &ash::Device) -> Result<u64> {
+        match &mut self.signal {
+            Signal::Semaphore(semaphore) => {
+                let value = unsafe { device.get_semaphore_counter_value(*semaphore) }?;
+                self.counter.observe(value);
+            }
+            Signal::Fences { pending, free } => {
+                while let Some(&(value, fence)) = pending.front() {
+                    if !unsafe { device.get_fence_status(fence) }? {
+                        break;
+                    }
+                    unsafe { device.reset_fences(&[fence]) }?;
+                    pending.pop_front();
+                    free.push(fence);
+                    self.counter.observe(value);
+                }
+            }
+        }
+        Ok(self.counter.completed())
+    }
+
+    /// Whether the submission that signals `value` is done, without blocking.
+    pub(crate) fn is_complete(&mut self, device: &ash::Device, value: u64) -> Result<bool> {
+        Ok(self.counter.is_complete(value) || self.completed(device)? >= value)
+    }
+
+    /// Blocks until the submission that signals `value` is done. Values past the latest
+    /// submission wait for that one, since nothing would ever signal them.
+    pub(crate) fn wait(&mut self, device: &ash::Device, value: u64) -> Result<()> {
+        let value = value.min(self.counter.submitted());
+        if self.counter.is_complete(value) {
+            return Ok(());
+        }
+
+        match &mut self.signal {
+            Signal::Semaphore(semaphore) => {
+                let semaphores = [*semaphore];
+                let values = [value];
+                let wait_info = vk::SemaphoreWaitInfo::builder()
+                    .semaphores(&semaphores)
+                    .values(&values);
+                unsafe { device.wait_semaphores(&wait_info, u64::MAX) }?;
+                self.counter.observe(value);
+            }
+            Signal::Fences { pending, .. } => {
+                // The fence of the submission, or of the first one after it, signals no earlier.
+                if let Some(&(_, fence)) = pending.iter().find(|(pending, _)| *pending >= value) {
+                    unsafe { device.wait_for_fences(&[fence], true, u64::MAX) }?;
+                }
+                self.completed(device)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for everything submitted and frees the semaphore or fences.
+    pub(crate) fn destroy(&mut self, device: &ash::Device) {
+        if let Err(e) = self.wait(device, self.counter.submitted()) {
+            error!("Failed to wait for timeline: {e}");
+        }
+
+        match &mut self.signal {
+            Signal::Semaphore(semaphore) => unsafe { device.destroy_semaphore(*semaphore, None) },
+            Signal::Fences { pending, free } => {
+                for (_, fence) in pending.drain(..) {
+                    unsafe { device.destroy_fence(fence, None) };
+                }
+                for fence in free.drain(..) {
+                    unsafe { device.destroy_fence(fence, None) };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::VecDeque;
+use std::ptr;
+
+use ash::vk;
+use gpu_allocator::vulkan;
+
+use super::buffers::create_buffer;
+use super::commands::TransferQueue;
+use super::error::{Result, VkcrError};
+use super::timeline::Timeline;
+
+/// Size of the staging ring uploads are copied through. Bigger uploads get a staging buffer of
+/// their own.
+pub const STAGING_SIZE: u64 = 16 << 20;
+
+/// Offsets in the ring are kept to this alignment, which satisfies
+/// `optimalBufferCopyOffsetAlignment` on common hardware.
+const STAGING_ALIGNMENT: u64 = 16;
+
+/// Space bookkeeping of a ring buffer that is filled at its head and freed from its tail, in the
+/// order it was allocated.
+#[derive(Clone, Debug)]
+pub struct StagingRing {
+    capacity: u64,
+    head: u64,
+    used: u64,
+}
+
+impl StagingRing {
+    pub fn new(capacity: u64) -> Self {
+        StagingRing {
+            capacity,
+            head: 0,
+            used: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Bytes allocated and not yet released, including what was skipped to wrap around.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Reserves `size` bytes at an `alignment` aligned offset. Returns the offset and how many
+    /// bytes to pass to [`StagingRing::release`] once the GPU is done with them, or `None` when
+    /// the ring is too full.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<(u64, u64)> {
+        let aligned = self.head.next_multiple_of(alignment);
+        // Allocations are contiguous, so one that doesn't fit before the end starts over at 0
+        // and the rest of the ring is skipped.
+        let offset = if aligned + size <= self.capacity {
+            aligned
+        } else {
+            0
+        };
+        let consumed = if offset == 0 && self.head != 0 {
+            self.capacity - self.head + size
+        } else {
+            offset + size - self.head
+        };
+
+        if size > self.capacity || self.used + consumed > self.capacity {
+            return None;
+        }
+
+        self.head = (offset + size) % self.capacity;
+        self.used += consumed;
+        Some((offset, consumed))
+    }
+
+    /// Frees the oldest `consumed` bytes.
+    pub fn release(&mut self, consumed: u64) {
+        self.used -= consumed;
+        if self.used == 0 {
+            self.head = 0;
+        }
+    }
+}
+
+/// Reports when the data of an upload is resident, through [`UploadManager::is_resident`] and
+/// [`UploadManager::wait`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadHandle(u64);
+
+/// Copies recorded into one command buffer and submitted together.
+struct Batch {
+    id: u64,
+    command_buffer: vk::CommandBuffer,
+    /// The transfer timeline value the batch signals, once submitted.
+    value: u64,
+    /// Ring bytes to release once the copies are done.
+    consumed: u64,
+    /// Staging buffers of uploads too big for the ring.
+    dedicated: Vec<(vk::Buffer, vulkan::Allocation)>,
+}
+
+/// Streams data to device-local buffers through a persistently mapped staging ring on the
+/// transfer queue. Uploads are recorded into a batch that is submitted by
+/// [`UploadManager::flush`]; submitted batches are tracked on the transfer queue's timeline
+/// and retired by [`UploadManager::poll`], which hands their staging space back.
+pub(crate) struct UploadManager {
+    transfer: TransferQueue,
+    timeline: Timeline,
+    staging_buffer: vk::Buffer,
+    staging_allocation: Option<vulkan::Allocation>,
+    ring: StagingRing,
+    recording: Option<Batch>,
+    in_flight: VecDeque<Batch>,
+    next_batch: u64,
+}
+
+impl UploadManager {
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        transfer: TransferQueue,
+        timeline: Timeline,
+        capacity: u64,
+    ) -> Result<Self> {
+        let (staging_buffer, staging_allocation) = create_buffer(
+            device,
+            allocator,
+            capacity,
+            "Staging Ring",
+            &[],
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+
+        Ok(UploadManager {
+            transfer,
+            timeline,
+            staging_buffer,
+            staging_allocation: Some(staging_allocation),
+            ring: StagingRing::new(capacity),
+            recording: None,
+            in_flight: VecDeque::new(),
+            next_batch: 0,
+        })
+    }
+
+    pub(crate) fn transfer(&self) -> &TransferQueue {
+        &self.transfer
+    }
+
+    /// Queues a copy of `data` to `dst` at `dst_offset` and returns right away. The copy is
+    /// submitted with the next [`UploadManager::flush`]; `dst` must not be used before the
+    /// returned handle is resident, except by submissions that wait for
+    /// [`UploadManager::frame_wait`].
+    pub(crate) fn upload(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        data: &[u8],
+        dst: vk::Buffer,
+        dst_offset: u64,
+    ) -> Result<UploadHandle> {
+        // Copies of zero bytes are invalid, so there is nothing to record.
+        if data.is_empty() {
+            return Ok(UploadHandle(self.batch(device)?.id));
+        }
+
+        let (src, src_offset) = self.stage(device, allocator, data)?;
+
+        let batch = self.batch(device)?;
+        record_copy(
+            device,
+            batch.command_buffer,
+            src,
+            src_offset,
+            dst,
+            dst_offset,
+            data.len() as u64,
+        );
+        Ok(UploadHandle(batch.id))
+    }
+
+    /// Queues a copy of `data` into the color image `dst` of `extent`, which leaves it in
+    /// `SHADER_READ_ONLY_OPTIMAL`. Like [`UploadManager::upload`], `dst` must not be used before
+    /// the returned handle is resident. It has to be shared with the queues that use it, since
+    /// the upload doesn't transfer ownership.
+    pub(crate) fn upload_image(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        data: &[u8],
+        dst: vk::Image,
+        extent: vk::Extent2D,
+    ) -> Result<UploadHandle> {
+        if data.is_empty() {
+            return Err(VkcrError::EmptyUpload {
+                name: "image".to_owned(),
+            });
+        }
+
+        let (src, src_offset) = self.stage(device, allocator, data)?;
+
+        let batch = self.batch(device)?;
+        record_image_copy(device, batch.command_buffer, src, src_offset, dst, extent);
+        Ok(UploadHandle(batch.id))
+    }
+
+    /// Submits the copies recorded since the last flush.
+    pub(crate) fn flush(&mut self, device: &ash::Device) -> Result<()> {
+        let Some(mut batch) = self.recording.take() else {
+            return Ok(());
+        };
+
+        unsafe { device.end_command_buffer(batch.command_buffer) }?;
+
+        batch.value = self.timeline.submit(
+            device,
+            self.transfer.queue,
+            &[batch.command_buffer],
+            &[],
+            &[],
+        )?;
+
+        self.in_flight.push_back(batch);
+        Ok(())
+    }
+
+    /// Retires the batches the GPU has finished, without blocking.
+    pub(crate) fn poll(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+    ) -> Result<()> {
+        let completed = self.timeline.completed(device)?;
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|batch| batch.value <= completed)
+        {
+            self.retire_oldest(device, allocator)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the upload behind `handle` has completed. Only changes on
+    /// [`UploadManager::poll`] and [`UploadManager::wait`], which retire batches in submission
+    /// order, so every earlier upload is resident too.
+    pub(crate) fn is_resident(&self, handle: UploadHandle) -> bool {
+        self.recording
+            .as_ref()
+            .is_none_or(|batch| batch.id != handle.0)
+            && self.in_flight.iter().all(|batch| batch.id != handle.0)
+    }
+
+    /// Submits the upload behind `handle` if it is still being recorded and blocks until it has
+    /// completed.
+    pub(crate) fn wait(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        handle: UploadHandle,
+    ) -> Result<()> {
+        if !self.is_resident(handle) {
+            self.flush(device)?;
+        }
+        while !self.is_resident(handle) {
+            self.retire_oldest(device, allocator)?;
+        }
+        Ok(())
+    }
+
+    /// Submits what is being recorded and blocks until every upload has completed.
+    pub(crate) fn finish(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+    ) -> Result<()> {
+        self.flush(device)?;
+        while !self.in_flight.is_empty() {
+            self.retire_oldest(device, allocator)?;
+        }
+        Ok(())
+    }
+
+    /// Submits what is being recorded and returns the transfer timeline semaphore and value a
+    /// submission on another queue has to wait for to see every upload so far. Devices without
+    /// timeline semaphores can't wait on the GPU, so this blocks until the uploads are done
+    /// instead and returns `None`.
+    pub(crate) fn frame_wait(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+    ) -> Result<Option<(vk::Semaphore, u64)>> {
+        self.flush(device)?;
+        match self.timeline.semaphore() {
+            Some(semaphore) => Ok(Some((semaphore, self.timeline.submitted()))),
+            None => {
+                self.finish(device, allocator)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Waits for every upload and frees the staging ring.
+    pub(crate) fn destroy(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+    ) -> Result<()> {
+        self.finish(device, allocator)?;
+
+        self.timeline.destroy(device);
+
+        unsafe { device.destroy_buffer(self.staging_buffer, None) };
+        if let Some(allocation) = self.staging_allocation.take() {
+            allocator.free(allocation)?;
+        }
+        Ok(())
+    }
+
+    /// Copies `data` to staging memory the batch being recorded frees once it is done, and
+    /// returns the buffer and offset it was written to.
+    fn stage(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+        data: &[u8],
+    ) -> Result<(vk::Buffer, u64)> {
+        let size = data.len() as u64;
+
+        if size > self.ring.capacity() {
+            let (buffer, allocation) = create_buffer(
+                device,
+                allocator,
+                size,
+                "Staging Buffer",
+                &[],
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+            write_mapped(&allocation, 0, data);
+
+            self.batch(device)?.dedicated.push((buffer, allocation));
+            return Ok((buffer, 0));
+        }
+
+        let (offset, consumed) = loop {
+            if let Some(allocation) = self.ring.allocate(size, STAGING_ALIGNMENT) {
+                break allocation;
+            }
+            // Everything recorded so far has to reach the GPU before its space can come back.
+            self.flush(device)?;
+            self.retire_oldest(device, allocator)?;
+        };
+
+        write_mapped(
+            self.staging_allocation
+                .as_ref()
+                .expect("Failed to get staging allocation"),
+            offset,
+            data,
+        );
+
+        self.batch(device)?.consumed += consumed;
+        Ok((self.staging_buffer, offset))
+    }
+
+    /// The batch being recorded, started on first use.
+    fn batch(&mut self, device: &ash::Device) -> Result<&mut Batch> {
+        if self.recording.is_none() {
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_pool(self.transfer.command_pool)
+                .command_buffer_count(1);
+            let command_buffer = *unsafe { device.allocate_command_buffers(&allocate_info) }?
+                .first()
+                .expect("Failed to get command buffer");
+
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            unsafe { device.begin_command_buffer(command_buffer, &begin_info) }?;
+
+            self.recording = Some(Batch {
+                id: self.next_batch,
+                command_buffer,
+                value: 0,
+                consumed: 0,
+                dedicated: Vec::new(),
+            });
+            self.next_batch += 1;
+        }
+
+        Ok(self.recording.as_mut().expect("Failed to get batch"))
+    }
+
+    /// Blocks until the oldest submitted batch is done and frees what it used.
+    fn retire_oldest(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut vulkan::Allocator,
+    ) -> Result<()> {
+        let Some(batch) = self.in_flight.pop_front() else {
+            return Ok(());
+        };
+
+        self.timeline.wait(device, batch.value)?;
+
+        unsafe { device.free_command_buffers(self.transfer.command_pool, &[batch.command_buffer]) };
+
+        self.ring.release(batch.consumed);
+
+        for (buffer, allocation) in batch.dedicated {
+            unsafe { device.destroy_buffer(buffer, None) };
+            allocator.free(allocation)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_mapped(allocation: &vulkan::Allocation, offset: u64, data: &[u8]) {
+    unsafe {
+        ptr::copy_nonoverlapping(
+            data.as_ptr(),
+            (allocation
+                .mapped_ptr()
+                .expect("Failed to get pointer")
+                .as_ptr() as *mut u8)
+                .add(offset as usize),
+            data.len(),
+        )
+    };
+}
+
+fn record_copy(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Buffer,
+    src_offset: u64,
+    dst: vk::Buffer,
+    dst_offset: u64,
+    size: u64,
+) {
+    let region = vk::BufferCopy::builder()
+        .src_offset(src_offset)
+        .dst_offset(dst_offset)
+        .size(size)
+        .build();
+
+    unsafe { device.cmd_copy_buffer(command_buffer, src, dst, &[region]) };
+}
+
+fn record_image_copy(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Buffer,
+    src_offset: u64,
+    dst: vk::Image,
+    extent: vk::Extent2D,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+
+    let to_transfer = vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(dst)
+        .subresource_range(*subresource_range);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[*to_transfer],
+        )
+    };
+
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(src_offset)
+        .image_subresource(vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            src,
+            dst,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[*region],
+        )
+    };
+
+    // A transfer-only queue has no fragment shader stage to wait for. Nothing samples the
+    // image before the upload is resident, so the layout change only has to happen.
+    let to_shader_read = vk::ImageMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(dst)
+        .subresource_range(*subresource_range);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[*to_shader_read],
+        )
+    };
+}
@@ -7,13 +7,15 @@ use memoffset::offset_of;
 #[derive(Clone, Copy)]
 pub struct Vertex {
     pub color: cgmath::Vector3<f32>,
-    pub pos: cgmath::Vector2<f32>,
+    pub pos: cgmath::Vector3<f32>,
+    pub uv: cgmath::Vector2<f32>,
 }
 
 impl Vertex {
+    /// Checked against the inputs of the vertex shader whenever a pipeline is created.
     pub fn get_descriptions() -> (
         vk::VertexInputBindingDescription,
-        [vk::VertexInputAttributeDescription; 2],
+        [vk::VertexInputAttributeDescription; 3],
     ) {
         let binding_description = vk::VertexInputBindingDescription::builder()
             .stride(size_of::<Vertex>() as u32)
@@ -23,15 +25,24 @@ impl Vertex {
         let position_attrib = vk::VertexInputAttributeDescription::builder()
             .binding(0)
             .location(0)
-            .format(vk::Format::R32G32_SFLOAT)
+            .format(vk::Format::R32G32B32_SFLOAT)
             .offset(offset_of!(Vertex, pos) as u32);
 
-        let uv_attrib = vk::VertexInputAttributeDescription::builder()
+        let color_attrib = vk::VertexInputAttributeDescription::builder()
             .binding(0)
             .location(1)
             .format(vk::Format::R32G32B32_SFLOAT)
             .offset(offset_of!(Vertex, color) as u32);
-        (*binding_description, [*position_attrib, *uv_attrib])
+
+        let uv_attrib = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(offset_of!(Vertex, uv) as u32);
+        (
+            *binding_description,
+            [*position_attrib, *color_attrib, *uv_attrib],
+        )
     }
 }
 
@@ -1,3 +1,8 @@
-pub fn read_file(path: &str) -> Vec<u8> {
-    std::fs::read(path).unwrap_or_else(|_| panic!("Failed to read {path}"))
-}
\ No newline at end of file
+use crate::core::error::{Result, VkcrError};
+
+pub fn read_file(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|source| VkcrError::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
@@ -1,5 +1,13 @@
-use vkcr::core::app::App;
+use vkcr::core::builder::AppBuilder;
+use vkcr::core::controller::CameraMode;
 
 fn main() {
-    App::init();
+    if let Err(e) = AppBuilder::new()
+        .title("VKCR")
+        .camera_controller(CameraMode::Orbit)
+        .run()
+    {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 }
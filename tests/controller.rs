@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use ash::vk;
+use cgmath::{InnerSpace, Transform};
+use winit::event::{DeviceId, ElementState, KeyboardInput, MouseScrollDelta, TouchPhase};
+use winit::event::{VirtualKeyCode, WindowEvent};
+
+use vkcr::core::controller::{CameraController, CameraMode};
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 200,
+    height: 100,
+};
+
+#[allow(deprecated)]
+fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+    WindowEvent::KeyboardInput {
+        device_id: unsafe { DeviceId::dummy() },
+        input: KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(key),
+            modifiers: Default::default(),
+        },
+        is_synthetic: false,
+    }
+}
+
+#[allow(deprecated)]
+fn scroll(lines: f32) -> WindowEvent<'static> {
+    WindowEvent::MouseWheel {
+        device_id: unsafe { DeviceId::dummy() },
+        delta: MouseScrollDelta::LineDelta(0.0, lines),
+        phase: TouchPhase::Moved,
+        modifiers: Default::default(),
+    }
+}
+
+#[test]
+fn fly_moves_forward_while_key_is_held() {
+    let mut controller = CameraController::new(CameraMode::Fly);
+    let start = controller.position;
+
+    controller.handle_event(&key(VirtualKeyCode::W, ElementState::Pressed));
+    controller.update(Duration::from_secs(1));
+
+    let moved = controller.position - start;
+    assert!((moved.magnitude() - controller.move_speed).abs() < 1e-4);
+    assert!(moved.normalize().dot(controller.forward()) > 0.999);
+
+    controller.handle_event(&key(VirtualKeyCode::W, ElementState::Released));
+    let stopped = controller.position;
+    controller.update(Duration::from_secs(1));
+    assert_eq!(controller.position, stopped);
+}
+
+#[test]
+fn orbit_keeps_the_target_centered_and_zooms_with_the_wheel() {
+    let mut controller = CameraController::new(CameraMode::Orbit);
+    let distance = controller.distance;
+
+    controller.handle_event(&scroll(1.0));
+    assert!(controller.distance < distance);
+    assert!(
+        ((controller.eye() - controller.target).magnitude() - controller.distance).abs() < 1e-4
+    );
+
+    let camera = controller.camera(EXTENT);
+    let target = camera.view.transform_point(controller.target);
+    assert!(target.x.abs() < 1e-4 && target.y.abs() < 1e-4 && target.z < 0.0);
+}
+
+#[test]
+fn ortho_projection_follows_the_extent() {
+    let controller = CameraController::new(CameraMode::Ortho2D);
+    let camera = controller.camera(EXTENT);
+
+    let corner = (camera.proj * camera.view).transform_point(cgmath::Point3::new(
+        controller.zoom * 2.0,
+        controller.zoom,
+        0.0,
+    ));
+    assert!((corner.x - 1.0).abs() < 1e-4);
+    assert!((corner.y - 1.0).abs() < 1e-4);
+}
@@ -0,0 +1,62 @@
+use ash::vk;
+
+use vkcr::core::device::{device_priority, matches_selector, missing_features, transfer_family};
+
+#[test]
+fn selector_matches_names_and_indices() {
+    let name = "llvmpipe (LLVM 15.0.7, 256 bits)";
+    assert!(matches_selector("llvmpipe", 1, name));
+    assert!(matches_selector("LLVMPIPE", 1, name));
+    assert!(matches_selector("1", 1, name));
+    assert!(!matches_selector("0", 1, name));
+    assert!(matches_selector("lavapipe", 1, name));
+    assert!(!matches_selector("lavapipe", 0, "NVIDIA GeForce RTX 3080"));
+}
+
+#[test]
+fn discrete_gpus_rank_first_then_memory() {
+    const GIB: u64 = 1 << 30;
+
+    let discrete = device_priority(vk::PhysicalDeviceType::DISCRETE_GPU, 2 * GIB);
+    let integrated = device_priority(vk::PhysicalDeviceType::INTEGRATED_GPU, 16 * GIB);
+    let cpu = device_priority(vk::PhysicalDeviceType::CPU, 64 * GIB);
+    assert!(discrete > integrated && integrated > cpu);
+
+    assert!(
+        device_priority(vk::PhysicalDeviceType::DISCRETE_GPU, 8 * GIB)
+            > device_priority(vk::PhysicalDeviceType::DISCRETE_GPU, 4 * GIB)
+    );
+}
+
+#[test]
+fn missing_features_are_named() {
+    let required = vk::PhysicalDeviceFeatures {
+        geometry_shader: vk::TRUE,
+        sampler_anisotropy: vk::TRUE,
+        ..Default::default()
+    };
+    let supported = vk::PhysicalDeviceFeatures {
+        sampler_anisotropy: vk::TRUE,
+        shader_int64: vk::TRUE,
+        ..Default::default()
+    };
+
+    assert_eq!(missing_features(&required, &supported), ["geometry_shader"]);
+    assert!(missing_features(&supported, &supported).is_empty());
+}
+
+#[test]
+fn uploads_prefer_queues_without_graphics() {
+    let family = |queue_flags| vk::QueueFamilyProperties {
+        queue_flags,
+        queue_count: 1,
+        ..Default::default()
+    };
+    let graphics = family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE);
+    let compute = family(vk::QueueFlags::COMPUTE);
+    let transfer = family(vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING);
+
+    assert_eq!(transfer_family(&[graphics, compute, transfer], 0), 2);
+    assert_eq!(transfer_family(&[graphics, compute], 0), 1);
+    assert_eq!(transfer_family(&[graphics], 0), 0);
+}
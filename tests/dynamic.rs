@@ -0,0 +1,29 @@
+use vkcr::core::dynamic::DeletionQueue;
+
+#[test]
+fn retired_items_wait_for_their_timeline_value() {
+    let mut queue = DeletionQueue::new();
+    queue.push(0, "unused");
+    queue.push(3, "first");
+    queue.push(3, "second");
+    queue.push(5, "third");
+
+    // Value 0 was never submitted, so nothing uses the item.
+    assert_eq!(queue.collect(0), vec!["unused"]);
+    assert!(queue.collect(2).is_empty());
+    assert_eq!(queue.collect(3), vec!["first", "second"]);
+    assert_eq!(queue.len(), 1);
+    assert!(queue.collect(4).is_empty());
+    assert_eq!(queue.collect(10), vec!["third"]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn draining_frees_everything() {
+    let mut queue = DeletionQueue::new();
+    queue.push(5, 1);
+    queue.push(6, 2);
+
+    assert_eq!(queue.drain().collect::<Vec<_>>(), vec![1, 2]);
+    assert!(queue.is_empty());
+}
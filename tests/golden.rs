@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use cgmath::SquareMatrix;
+
+use vkcr::core::camera::Camera;
+use vkcr::core::error::VkcrError;
+use vkcr::core::geometry::{QUAD_INDICES, QUAD_VERTICES};
+use vkcr::core::golden::{check_golden, compare, render_scene, Scene, SKIP_GPU_TESTS};
+use vkcr::core::headless;
+
+const TOLERANCE: u8 = 2;
+
+fn assert_scene_matches(name: &str, transform: cgmath::Matrix4<f32>) {
+    if !headless::is_supported() {
+        if std::env::var(SKIP_GPU_TESTS).unwrap_or_else(|_| "0".to_string()) == "1" {
+            eprintln!("Skipping {name}: no Vulkan device available");
+            return;
+        }
+        panic!("No Vulkan device available to render {name}. Run with {SKIP_GPU_TESTS}=1 to skip");
+    }
+
+    let image = render_scene(Scene {
+        width: 64,
+        height: 64,
+        vertices: &QUAD_VERTICES,
+        indices: &QUAD_INDICES,
+        transform,
+        camera: Camera {
+            view: cgmath::Matrix4::identity(),
+            proj: cgmath::Matrix4::identity(),
+        },
+    })
+    .unwrap_or_else(|e| panic!("Failed to render {name}: {e}"));
+
+    let golden_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+
+    if let Err(e) = check_golden(&image, &golden_path, TOLERANCE) {
+        panic!("{e}");
+    }
+}
+
+#[test]
+fn quad_matches_golden() {
+    assert_scene_matches("quad", cgmath::Matrix4::identity());
+}
+
+#[test]
+fn rotated_quad_matches_golden() {
+    assert_scene_matches(
+        "quad_rotated",
+        cgmath::Matrix4::from_angle_z(cgmath::Deg(90.0)),
+    );
+}
+
+#[test]
+fn compare_accepts_differences_within_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let actual = image::RgbaImage::from_pixel(4, 4, image::Rgba([102, 98, 100, 255]));
+
+    let comparison = compare(&actual, &expected, TOLERANCE);
+
+    assert_eq!(comparison.mismatched_pixels, 0);
+    assert_eq!(comparison.max_difference, 2);
+}
+
+#[test]
+fn compare_marks_mismatched_pixels_in_diff() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 2, image::Rgba([0, 50, 0, 255]));
+
+    let comparison = compare(&actual, &expected, TOLERANCE);
+
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.max_difference, 50);
+    assert_eq!(
+        *comparison.diff.get_pixel(1, 2),
+        image::Rgba([255, 0, 0, 255])
+    );
+    assert_eq!(
+        *comparison.diff.get_pixel(0, 0),
+        image::Rgba([0, 0, 0, 255])
+    );
+}
+
+#[test]
+fn check_golden_writes_actual_and_diff_on_mismatch() {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&directory).expect("Failed to create golden directory");
+
+    let golden_path = directory.join("mismatch.png");
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]))
+        .save(&golden_path)
+        .expect("Failed to write golden image");
+
+    let actual = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 255, 255, 255]));
+
+    match check_golden(&actual, &golden_path, TOLERANCE) {
+        Err(VkcrError::GoldenMismatch { reason, diff, .. }) => {
+            assert!(reason.contains("16 pixels differ"), "{reason}");
+            assert!(diff.is_some());
+        }
+        Err(e) => panic!("Expected a golden mismatch, got {e}"),
+        Ok(()) => panic!("Mismatching images must fail the check"),
+    }
+    assert!(directory.join("mismatch.actual.png").exists());
+    assert!(directory.join("mismatch.diff.png").exists());
+}
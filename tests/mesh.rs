@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+use vkcr::core::error::VkcrError;
+use vkcr::core::mesh::Mesh;
+
+/// One triangle with positions, UVs and `u16` indices in an embedded buffer.
+const TRIANGLE_GLTF: &str = r#"{
+    "asset": { "version": "2.0" },
+    "buffers": [{
+        "byteLength": 68,
+        "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAABAAIAAAA="
+    }],
+    "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+        { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+        { "buffer": 0, "byteOffset": 60, "byteLength": 6 }
+    ],
+    "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+          "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] },
+        { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+        { "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }
+    ],
+    "meshes": [{
+        "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "indices": 2 }]
+    }]
+}"#;
+
+const QUAD_OBJ: &str = "\
+v -1.0 -1.0 0.0
+v 1.0 -1.0 0.0
+v 1.0 1.0 0.0
+v -1.0 1.0 0.0
+vt 0.0 0.0
+vt 1.0 0.0
+vt 1.0 1.0
+vt 0.0 1.0
+f 1/1 2/2 3/3 4/4
+";
+
+fn write_fixture(name: &str, contents: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, contents).expect("Failed to write fixture");
+    path.to_str().expect("Fixture path is not UTF-8").to_owned()
+}
+
+#[test]
+fn loads_gltf_with_indices_and_uvs() {
+    let mesh = Mesh::load(&write_fixture("triangle.gltf", TRIANGLE_GLTF)).expect("Failed to load");
+
+    assert_eq!(mesh.indices, [0, 1, 2]);
+    assert_eq!(mesh.vertices.len(), 3);
+    assert_eq!(mesh.vertices[1].pos, cgmath::Vector3::new(1.0, 0.0, 0.0));
+    assert_eq!(mesh.vertices[2].uv, cgmath::Vector2::new(0.0, 1.0));
+    assert_eq!(mesh.vertices[0].color, cgmath::Vector3::new(1.0, 1.0, 1.0));
+}
+
+#[test]
+fn loads_and_triangulates_obj() {
+    let mesh = Mesh::load(&write_fixture("quad.obj", QUAD_OBJ)).expect("Failed to load");
+
+    assert_eq!(mesh.vertices.len(), 4);
+    assert_eq!(mesh.indices.len(), 6);
+    assert_eq!(mesh.vertices[2].pos, cgmath::Vector3::new(1.0, 1.0, 0.0));
+    // OBJ puts the UV origin at the bottom left, Vulkan samples from the top left.
+    assert_eq!(mesh.vertices[3].uv, cgmath::Vector2::new(0.0, 0.0));
+}
+
+#[test]
+fn rejects_unknown_extensions() {
+    match Mesh::load("model.fbx") {
+        Err(VkcrError::Mesh { path, .. }) => assert_eq!(path, "model.fbx"),
+        Err(e) => panic!("Unexpected error: {e}"),
+        Ok(_) => panic!("Loaded an unsupported file"),
+    }
+}
@@ -0,0 +1,54 @@
+use ash::vk;
+
+use vkcr::core::pipeline_cache::header_matches;
+
+fn properties() -> vk::PhysicalDeviceProperties {
+    vk::PhysicalDeviceProperties {
+        vendor_id: 0x10de,
+        device_id: 0x2484,
+        pipeline_cache_uuid: [7; 16],
+        ..Default::default()
+    }
+}
+
+fn header(vendor_id: u32, device_id: u32, uuid: [u8; 16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(32u32.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+    data.extend(vendor_id.to_le_bytes());
+    data.extend(device_id.to_le_bytes());
+    data.extend(uuid);
+    data.extend([0xab; 64]);
+    data
+}
+
+#[test]
+fn cache_from_the_same_device_is_accepted() {
+    assert!(header_matches(
+        &header(0x10de, 0x2484, [7; 16]),
+        &properties()
+    ));
+}
+
+#[test]
+fn cache_from_another_device_or_driver_is_rejected() {
+    let properties = properties();
+
+    assert!(!header_matches(
+        &header(0x1002, 0x2484, [7; 16]),
+        &properties
+    ));
+    assert!(!header_matches(
+        &header(0x10de, 0x1234, [7; 16]),
+        &properties
+    ));
+    assert!(!header_matches(
+        &header(0x10de, 0x2484, [8; 16]),
+        &properties
+    ));
+    assert!(!header_matches(
+        &header(0x10de, 0x2484, [7; 16])[..20],
+        &properties
+    ));
+    assert!(!header_matches(&[], &properties));
+}
@@ -0,0 +1,238 @@
+use ash::vk;
+
+use vkcr::core::reflect::{ShaderLayout, ShaderReflection};
+use vkcr::core::shader_compiler;
+use vkcr::core::vertex::Vertex;
+
+fn reflect_vertex_shader(name: &str, source: &str) -> ShaderLayout {
+    let path = std::env::temp_dir().join(format!("vkcr_reflect_{name}.vert"));
+    std::fs::write(&path, source).unwrap();
+
+    let code = shader_compiler::compile_file(&path).unwrap().code;
+    ShaderLayout::from_stages(&[ShaderReflection::from_spirv(&code).unwrap()]).unwrap()
+}
+
+#[test]
+fn default_shader_layout_matches_the_engine() {
+    let layout = ShaderLayout::load("assets/shaders/default").unwrap();
+
+    let bindings: Vec<_> = layout
+        .bindings
+        .iter()
+        .map(|b| (b.set, b.binding, b.descriptor_type, b.count, b.stages))
+        .collect();
+    assert_eq!(
+        bindings,
+        [
+            (
+                0,
+                0,
+                vk::DescriptorType::UNIFORM_BUFFER,
+                1,
+                vk::ShaderStageFlags::VERTEX
+            ),
+            (
+                0,
+                1,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                1,
+                vk::ShaderStageFlags::FRAGMENT
+            ),
+        ]
+    );
+
+    assert_eq!(layout.push_constants.len(), 1);
+    assert_eq!(layout.push_constants[0].size, 64);
+    assert_eq!(
+        layout.push_constant_stages(0, 64),
+        vk::ShaderStageFlags::VERTEX
+    );
+
+    let (_, attributes) = Vertex::get_descriptions();
+    assert_eq!(layout.vertex_inputs.len(), 3);
+    layout.validate_vertex_input(&attributes).unwrap();
+}
+
+#[test]
+fn mismatched_vertex_inputs_are_reported() {
+    let (_, attributes) = Vertex::get_descriptions();
+
+    let layout = reflect_vertex_shader(
+        "int_uv",
+        "#version 450\n\
+         layout(location = 0) in vec3 position;\n\
+         layout(location = 2) in ivec2 uv;\n\
+         void main() { gl_Position = vec4(position, float(uv.x)); }\n",
+    );
+    let error = layout.validate_vertex_input(&attributes).unwrap_err();
+    assert!(
+        error.contains("`uv`") && error.contains("location 2"),
+        "{error}"
+    );
+
+    let layout = reflect_vertex_shader(
+        "normal",
+        "#version 450\n\
+         layout(location = 0) in vec4 position;\n\
+         layout(location = 3) in vec3 normal;\n\
+         void main() { gl_Position = position + vec4(normal, 0.0); }\n",
+    );
+    let error = layout.validate_vertex_input(&attributes).unwrap_err();
+    assert!(
+        error.contains("`normal`") && error.contains("location 3"),
+        "{error}"
+    );
+}
+
+#[test]
+fn double_vertex_inputs_take_two_locations_past_two_components() {
+    let layout = reflect_vertex_shader(
+        "doubles",
+        "#version 450\n\
+         layout(location = 0) in dvec4 position;\n\
+         layout(location = 2) in dmat3x2 basis;\n\
+         layout(location = 5) in dmat3 rotation;\n\
+         void main() { gl_Position = vec4(vec3(rotation * position.xyz) + vec3(basis[2], 0.0), 1.0); }\n",
+    );
+
+    let inputs: Vec<_> = layout
+        .vertex_inputs
+        .iter()
+        .map(|input| (input.location, input.format))
+        .collect();
+    assert_eq!(
+        inputs,
+        [
+            (0, vk::Format::R64G64B64A64_SFLOAT),
+            (2, vk::Format::R64G64_SFLOAT),
+            (3, vk::Format::R64G64_SFLOAT),
+            (4, vk::Format::R64G64_SFLOAT),
+            (5, vk::Format::R64G64B64_SFLOAT),
+            (7, vk::Format::R64G64B64_SFLOAT),
+            (9, vk::Format::R64G64B64_SFLOAT),
+        ]
+    );
+}
+
+#[test]
+fn vertex_input_widths_must_match() {
+    let (_, attributes) = Vertex::get_descriptions();
+
+    let layout = reflect_vertex_shader(
+        "double_position",
+        "#version 450\n\
+         layout(location = 0) in dvec3 position;\n\
+         void main() { gl_Position = vec4(position, 1.0); }\n",
+    );
+    let error = layout.validate_vertex_input(&attributes).unwrap_err();
+    assert!(
+        error.contains("`position`") && error.contains("location 0"),
+        "{error}"
+    );
+
+    let attribute = vk::VertexInputAttributeDescription {
+        location: 0,
+        binding: 0,
+        format: vk::Format::R64G64_SFLOAT,
+        offset: 0,
+    };
+    let layout = reflect_vertex_shader(
+        "double_components",
+        "#version 450\n\
+         layout(location = 0) in dvec2 position;\n\
+         void main() { gl_Position = vec4(position, 0.0, 1.0); }\n",
+    );
+    layout.validate_vertex_input(&[attribute]).unwrap();
+
+    let layout = reflect_vertex_shader(
+        "double_missing_components",
+        "#version 450\n\
+         layout(location = 0) in dvec3 position;\n\
+         void main() { gl_Position = vec4(position, 1.0); }\n",
+    );
+    let error = layout.validate_vertex_input(&[attribute]).unwrap_err();
+    assert!(error.contains("location 0"), "{error}");
+}
+
+#[test]
+fn push_constant_ranges_follow_member_offsets() {
+    let layout = reflect_vertex_shader(
+        "push",
+        "#version 450\n\
+         layout(push_constant) uniform Push { layout(offset = 16) vec4 tint; mat4 model; } push;\n\
+         void main() { gl_Position = push.model * push.tint; }\n",
+    );
+
+    let range = layout.push_constants[0];
+    assert_eq!((range.offset, range.size), (16, 80));
+    assert_eq!(
+        layout.push_constant_stages(0, 64),
+        vk::ShaderStageFlags::empty()
+    );
+    assert!(layout.vertex_inputs.is_empty());
+}
+
+#[test]
+fn materials_must_fit_the_main_layout() {
+    let base = ShaderLayout::load("assets/shaders/default").unwrap();
+
+    let layout = reflect_vertex_shader(
+        "material",
+        "#version 450\n\
+         layout(binding = 0) uniform Camera { mat4 view; mat4 proj; } camera;\n\
+         layout(push_constant) uniform Push { mat4 model; } push;\n\
+         layout(location = 0) in vec3 position;\n\
+         void main() { gl_Position = camera.proj * camera.view * push.model * vec4(position, 1.0); }\n",
+    );
+    layout.check_compatible(&base).unwrap();
+
+    let layout = reflect_vertex_shader(
+        "extra_binding",
+        "#version 450\n\
+         layout(set = 1, binding = 0) uniform Extra { vec4 offset; } extra;\n\
+         void main() { gl_Position = extra.offset; }\n",
+    );
+    let error = layout.check_compatible(&base).unwrap_err();
+    assert!(error.contains("set 1 binding 0"), "{error}");
+}
+
+#[test]
+fn compute_shaders_report_storage_descriptors() {
+    let path = std::env::temp_dir().join("vkcr_reflect_particles.comp");
+    std::fs::write(
+        &path,
+        "#version 450\n\
+         layout(local_size_x = 64) in;\n\
+         layout(set = 0, binding = 0) buffer Particles { vec4 positions[]; } particles;\n\
+         layout(set = 0, binding = 1, rgba8) uniform writeonly image2D density;\n\
+         layout(push_constant) uniform Push { float delta; } push;\n\
+         void main() {\n\
+             uint i = gl_GlobalInvocationID.x;\n\
+             particles.positions[i].y -= push.delta;\n\
+             imageStore(density, ivec2(i, 0), vec4(1.0));\n\
+         }\n",
+    )
+    .unwrap();
+
+    let code = shader_compiler::compile_file(&path).unwrap().code;
+    let reflection = ShaderReflection::from_spirv(&code).unwrap();
+    assert_eq!(reflection.stage, vk::ShaderStageFlags::COMPUTE);
+
+    let layout = ShaderLayout::from_stages(&[reflection]).unwrap();
+    let bindings: Vec<_> = layout
+        .bindings
+        .iter()
+        .map(|b| (b.set, b.binding, b.descriptor_type))
+        .collect();
+    assert_eq!(
+        bindings,
+        [
+            (0, 0, vk::DescriptorType::STORAGE_BUFFER),
+            (0, 1, vk::DescriptorType::STORAGE_IMAGE),
+        ]
+    );
+    assert_eq!(
+        layout.push_constant_stages(0, 4),
+        vk::ShaderStageFlags::COMPUTE
+    );
+}
@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use vkcr::core::shader_compiler;
+
+fn write_shaders(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(dir);
+    std::fs::create_dir_all(dir.join("include")).unwrap();
+    for (name, source) in files {
+        std::fs::write(dir.join(name), source).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn includes_resolve_relative_to_the_including_file() {
+    let dir = write_shaders(
+        "vkcr_shader_include",
+        &[
+            (
+                "tint.frag",
+                "#version 450\n\
+                 #include \"include/tint.glsl\"\n\
+                 layout(location = 0) out vec4 outColor;\n\
+                 void main() { outColor = tint(); }\n",
+            ),
+            (
+                "include/tint.glsl",
+                "#include \"white.glsl\"\nvec4 tint() { return WHITE; }\n",
+            ),
+            ("include/white.glsl", "#define WHITE vec4(1.0)\n"),
+        ],
+    );
+
+    let compiled = shader_compiler::compile_to_spv(&dir.join("tint.frag")).unwrap();
+    assert_eq!(compiled.code[0], 0x0723_0203);
+    assert_eq!(
+        compiled.includes,
+        [
+            dir.join("include/tint.glsl"),
+            dir.join("include/white.glsl")
+        ]
+    );
+    assert!(dir.join("tint_f.spv").exists());
+}
+
+#[test]
+fn errors_point_at_the_file_and_line() {
+    let dir = write_shaders(
+        "vkcr_shader_errors",
+        &[
+            (
+                "broken.vert",
+                "#version 450\n\nvoid main() {\n    gl_Position = missing;\n}\n",
+            ),
+            (
+                "header.vert",
+                "#version 450\n#include \"include/broken.glsl\"\nvoid main() {}\n",
+            ),
+            ("include/broken.glsl", "\nfloat value = missing;\n"),
+        ],
+    );
+
+    let error = shader_compiler::compile_file(&dir.join("broken.vert"))
+        .err()
+        .unwrap();
+    assert_eq!(error.path, dir.join("broken.vert"));
+    assert_eq!(error.line, Some(4));
+    assert!(error.message.contains("missing"));
+
+    let error = shader_compiler::compile_file(&dir.join("header.vert"))
+        .err()
+        .unwrap();
+    assert_eq!(error.path, dir.join("include/broken.glsl"));
+    assert_eq!(error.line, Some(2));
+}
+
+#[test]
+fn stage_and_output_follow_the_file_name() {
+    assert!(shader_compiler::shader_kind(Path::new("default.vert")).is_some());
+    assert!(shader_compiler::shader_kind(Path::new("sky.frag.hlsl")).is_some());
+    assert!(shader_compiler::shader_kind(Path::new("notes.txt")).is_none());
+    assert_eq!(
+        shader_compiler::spv_path(Path::new("shaders/sky.frag.hlsl")),
+        Some(PathBuf::from("shaders/sky_f.spv"))
+    );
+}
@@ -0,0 +1,139 @@
+use ash::vk;
+
+use vkcr::core::swapchain::{
+    choose_extent, choose_image_count, choose_present_mode, choose_surface_format, ColorOutput,
+    PresentMode,
+};
+
+#[test]
+fn present_modes_fall_back_to_fifo() {
+    let all = [
+        vk::PresentModeKHR::IMMEDIATE,
+        vk::PresentModeKHR::MAILBOX,
+        vk::PresentModeKHR::FIFO,
+    ];
+    assert_eq!(
+        choose_present_mode(PresentMode::Vsync, &all),
+        vk::PresentModeKHR::FIFO
+    );
+    assert_eq!(
+        choose_present_mode(PresentMode::LowLatency, &all),
+        vk::PresentModeKHR::MAILBOX
+    );
+    assert_eq!(
+        choose_present_mode(PresentMode::Uncapped, &all),
+        vk::PresentModeKHR::IMMEDIATE
+    );
+
+    let fifo_only = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::FIFO_RELAXED];
+    assert_eq!(
+        choose_present_mode(PresentMode::LowLatency, &fifo_only),
+        vk::PresentModeKHR::FIFO
+    );
+    assert_eq!(
+        choose_present_mode(
+            PresentMode::Uncapped,
+            &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO]
+        ),
+        vk::PresentModeKHR::MAILBOX
+    );
+}
+
+#[test]
+fn image_counts_stay_within_the_surface_limits() {
+    assert_eq!(choose_image_count(None, 2, 8), 3);
+    assert_eq!(choose_image_count(Some(4), 2, 8), 4);
+    assert_eq!(choose_image_count(Some(1), 2, 8), 2);
+    assert_eq!(choose_image_count(Some(16), 2, 8), 8);
+    assert_eq!(choose_image_count(None, 3, 3), 3);
+    // A maximum of 0 means unlimited.
+    assert_eq!(choose_image_count(Some(16), 2, 0), 16);
+}
+
+#[test]
+fn extents_follow_the_surface_or_clamp_the_window() {
+    let extent = |width, height| vk::Extent2D { width, height };
+    let mut capabilities = vk::SurfaceCapabilitiesKHR {
+        current_extent: extent(800, 600),
+        min_image_extent: extent(1, 1),
+        max_image_extent: extent(4096, 4096),
+        ..Default::default()
+    };
+
+    // The surface decides.
+    assert_eq!(
+        choose_extent(&capabilities, extent(1024, 768)),
+        extent(800, 600)
+    );
+
+    // The window decides, within the limits of the surface.
+    capabilities.current_extent = extent(u32::MAX, u32::MAX);
+    assert_eq!(
+        choose_extent(&capabilities, extent(1024, 768)),
+        extent(1024, 768)
+    );
+    assert_eq!(
+        choose_extent(&capabilities, extent(8000, 0)),
+        extent(4096, 1)
+    );
+
+    // Minimized windows report a zero extent, which the caller has to wait out.
+    capabilities.current_extent = extent(0, 0);
+    assert_eq!(choose_extent(&capabilities, extent(0, 0)), extent(0, 0));
+}
+
+#[test]
+fn surface_formats_follow_the_preferences() {
+    let format = |format, color_space| vk::SurfaceFormatKHR {
+        format,
+        color_space,
+    };
+    let srgb = format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+    let hdr10 = format(
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    );
+    let scrgb = format(
+        vk::Format::R16G16B16A16_SFLOAT,
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+    );
+    let supported = [
+        format(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        ),
+        srgb,
+        hdr10,
+        scrgb,
+    ];
+
+    assert_eq!(
+        choose_surface_format(&[ColorOutput::Hdr10, ColorOutput::ScRgb], &supported),
+        (hdr10, ColorOutput::Hdr10)
+    );
+    assert_eq!(
+        choose_surface_format(&[ColorOutput::ScRgb], &supported),
+        (scrgb, ColorOutput::ScRgb)
+    );
+    assert_eq!(
+        choose_surface_format(&[ColorOutput::Hdr10], &supported[..2]),
+        (srgb, ColorOutput::Srgb)
+    );
+
+    // A format paired with another color space doesn't count.
+    let hdr10_format_only = [
+        format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        ),
+        srgb,
+    ];
+    assert_eq!(
+        choose_surface_format(&[ColorOutput::Hdr10], &hdr10_format_only),
+        (srgb, ColorOutput::Srgb)
+    );
+    assert_eq!(
+        choose_surface_format(&[], &supported[..1]),
+        (supported[0], ColorOutput::Srgb)
+    );
+}
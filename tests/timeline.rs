@@ -0,0 +1,30 @@
+use vkcr::core::timeline::TimelineCounter;
+
+#[test]
+fn counter_values_only_go_up() {
+    let mut counter = TimelineCounter::new();
+    assert_eq!(counter.next(), 1);
+    assert!(counter.is_complete(0));
+    assert!(!counter.is_complete(1));
+
+    assert_eq!(counter.submit(), 1);
+    assert_eq!(counter.submit(), 2);
+    assert_eq!(counter.submit(), 3);
+    assert_eq!(counter.submitted(), 3);
+    assert_eq!(counter.next(), 4);
+
+    counter.observe(2);
+    assert_eq!(counter.completed(), 2);
+    assert!(counter.is_complete(1));
+    assert!(counter.is_complete(2));
+    assert!(!counter.is_complete(3));
+
+    // An older reading doesn't move the counter back.
+    counter.observe(1);
+    assert_eq!(counter.completed(), 2);
+
+    // Nothing past the latest submission can have completed.
+    counter.observe(10);
+    assert_eq!(counter.completed(), 3);
+    assert!(!counter.is_complete(4));
+}
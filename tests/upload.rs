@@ -0,0 +1,56 @@
+use vkcr::core::error::VkcrError;
+use vkcr::core::geometry::QUAD_VERTICES;
+use vkcr::core::golden::SKIP_GPU_TESTS;
+use vkcr::core::headless::{self, Headless};
+use vkcr::core::upload::StagingRing;
+
+#[test]
+fn ring_allocations_are_aligned_and_wrap_around() {
+    let mut ring = StagingRing::new(64);
+
+    assert_eq!(ring.allocate(40, 16), Some((0, 40)));
+    // Skips to the next 16 byte boundary and counts the padding as used.
+    assert_eq!(ring.allocate(8, 16), Some((48, 16)));
+    assert_eq!(ring.used(), 56);
+
+    // Doesn't fit before the end, so it starts over at 0 once the first allocation is released
+    // and the last 8 bytes are skipped.
+    assert_eq!(ring.allocate(16, 16), None);
+    ring.release(40);
+    assert_eq!(ring.allocate(16, 16), Some((0, 24)));
+
+    // The second allocation is still in use.
+    assert_eq!(ring.allocate(32, 16), None);
+    ring.release(16);
+    assert_eq!(ring.allocate(32, 16), Some((16, 32)));
+}
+
+#[test]
+fn ring_never_hands_out_space_in_use() {
+    let mut ring = StagingRing::new(64);
+
+    assert_eq!(ring.allocate(40, 16), Some((0, 40)));
+    assert_eq!(ring.allocate(40, 16), None);
+    assert_eq!(ring.allocate(65, 16), None);
+
+    ring.release(40);
+    assert_eq!(ring.used(), 0);
+    assert_eq!(ring.allocate(64, 16), Some((0, 64)));
+}
+
+#[test]
+fn empty_uploads_are_rejected() {
+    if !headless::is_supported() {
+        if std::env::var(SKIP_GPU_TESTS).unwrap_or_else(|_| "0".to_string()) == "1" {
+            eprintln!("Skipping empty_uploads_are_rejected: no Vulkan device available");
+            return;
+        }
+        panic!("No Vulkan device available. Run with {SKIP_GPU_TESTS}=1 to skip");
+    }
+
+    match Headless::with_geometry(4, 4, &QUAD_VERTICES, &[]) {
+        Err(VkcrError::EmptyUpload { name }) => assert_eq!(name, "Index Buffer"),
+        Err(e) => panic!("Expected an empty upload error, got {e}"),
+        Ok(_) => panic!("Expected an empty upload error"),
+    }
+}